@@ -0,0 +1,874 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+use crate::{
+    docker::{DockerComposeFile, DockerComposeService},
+    paths::AssetPath,
+    utils::{get_config_dirpath, write_file_in_place},
+};
+
+pub fn get_service_assets_dirpath(scene_name: &str, service_id: &str) -> PathBuf {
+    get_config_dirpath()
+        .join("scenes")
+        .join(scene_name)
+        .join(service_id)
+}
+
+/// In the short volume syntax, sources that are not paths are named volumes.
+/// Only relative paths can point into the assets folders of the scene.
+fn is_relative_volume_source(source: &str) -> bool {
+    source == "." || source == ".." || source.starts_with("./") || source.starts_with("../")
+}
+
+/// Sources of long syntax bind mounts are always paths, compose resolves the
+/// ones that are not absolute against the scene folder
+fn is_relative_bind_source(source: &str) -> bool {
+    !Path::new(source).is_absolute() && !source.starts_with('~')
+}
+
+/// Points a path relative to the scene folder at the assets folder of another
+/// service, returning `None` when the path is not inside the previous one
+fn rewrite_asset_path(path: &str, previous_service_id: &str, service_id: &str) -> Option<String> {
+    let (prefix, relative_path) = match path.strip_prefix("./") {
+        Some(relative_path) => ("./", relative_path),
+        None => ("", path),
+    };
+
+    let rest = relative_path.strip_prefix(previous_service_id)?;
+    if !rest.is_empty() && !rest.starts_with('/') {
+        return None;
+    }
+
+    Some(format!("{prefix}{service_id}{rest}"))
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AssetPathRewrite {
+    /// Key of the service definition holding the path
+    pub field: String,
+    pub previous: String,
+    pub current: String,
+}
+
+/// Rewrites a path value in place, recording the rewrite
+fn rewrite_asset_path_value(
+    field: &str,
+    path: &mut String,
+    previous_service_id: &str,
+    service_id: &str,
+    rewrites: &mut Vec<AssetPathRewrite>,
+) {
+    if let Some(rewritten_path) = rewrite_asset_path(path, previous_service_id, service_id) {
+        rewrites.push(AssetPathRewrite {
+            field: field.to_string(),
+            previous: std::mem::replace(path, rewritten_path.clone()),
+            current: rewritten_path,
+        });
+    }
+}
+
+/// Rewrites the paths of a service pointing into the assets folder of
+/// `previous_service_id`: bind mounts in both the short and the long volume
+/// syntax, env files and build contexts. Short syntax volume sources must be
+/// written as relative paths, `<service_id>` alone being a named volume.
+pub fn rewrite_service_asset_paths(
+    service: &mut DockerComposeService,
+    previous_service_id: &str,
+    service_id: &str,
+) -> Vec<AssetPathRewrite> {
+    let mut rewrites = vec![];
+
+    if let Some(Value::Sequence(volumes)) = service.extra.get_mut("volumes") {
+        for volume in volumes {
+            match volume {
+                Value::String(volume) => {
+                    let (source, rest) = match volume.split_once(':') {
+                        Some((source, rest)) => (source, Some(rest)),
+                        None => (volume.as_str(), None),
+                    };
+                    if !is_relative_volume_source(source) {
+                        continue;
+                    }
+                    if let Some(source) =
+                        rewrite_asset_path(source, previous_service_id, service_id)
+                    {
+                        let rewritten_volume = match rest {
+                            Some(rest) => format!("{source}:{rest}"),
+                            None => source,
+                        };
+                        rewrites.push(AssetPathRewrite {
+                            field: "volumes".to_string(),
+                            previous: std::mem::replace(volume, rewritten_volume.clone()),
+                            current: rewritten_volume,
+                        });
+                    }
+                }
+                Value::Mapping(volume) => {
+                    if volume.get("type").and_then(Value::as_str) != Some("bind") {
+                        continue;
+                    }
+                    if let Some(Value::String(source)) = volume.get_mut("source") {
+                        if !is_relative_bind_source(source) {
+                            continue;
+                        }
+                        rewrite_asset_path_value(
+                            "volumes",
+                            source,
+                            previous_service_id,
+                            service_id,
+                            &mut rewrites,
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let env_file_paths = match service.extra.get_mut("env_file") {
+        Some(Value::String(path)) => vec![path],
+        Some(Value::Sequence(env_files)) => env_files
+            .iter_mut()
+            .filter_map(|env_file| match env_file {
+                Value::String(path) => Some(path),
+                Value::Mapping(env_file) => match env_file.get_mut("path") {
+                    Some(Value::String(path)) => Some(path),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    };
+    for path in env_file_paths {
+        rewrite_asset_path_value(
+            "env_file",
+            path,
+            previous_service_id,
+            service_id,
+            &mut rewrites,
+        );
+    }
+
+    // The dockerfile path is relative to the context, so it never needs a rewrite
+    let build_context = match service.extra.get_mut("build") {
+        Some(Value::String(context)) => Some(context),
+        Some(Value::Mapping(build)) => match build.get_mut("context") {
+            Some(Value::String(context)) => Some(context),
+            _ => None,
+        },
+        _ => None,
+    };
+    if let Some(context) = build_context {
+        rewrite_asset_path_value(
+            "build",
+            context,
+            previous_service_id,
+            service_id,
+            &mut rewrites,
+        );
+    }
+
+    rewrites
+}
+
+/// Copies the whole assets folder of a service into the one of another
+/// service, which must not exist yet. Services without assets get an empty
+/// folder, as if they had just been created.
+pub fn copy_service_assets(
+    scene_name: &str,
+    service_id: &str,
+    target_scene_name: &str,
+    target_service_id: &str,
+) -> Result<(), String> {
+    let assets_dirpath = get_service_assets_dirpath(scene_name, service_id);
+    let target_assets_dirpath = get_service_assets_dirpath(target_scene_name, target_service_id);
+
+    match target_assets_dirpath.try_exists() {
+        Err(err) => {
+            return Err(format!(
+                "Cannot read target path {}: {err}",
+                target_assets_dirpath.to_str().unwrap()
+            ))
+        }
+        Ok(true) => {
+            return Err(format!(
+                "Assets folder {} already exists",
+                target_assets_dirpath.to_str().unwrap()
+            ))
+        }
+        _ => {}
+    }
+
+    fs::create_dir(&target_assets_dirpath).map_err(|err| {
+        format!(
+            "Cannot create local assets directory at {} for service {target_service_id} in scene {target_scene_name}: {err}",
+            target_assets_dirpath.to_str().unwrap(),
+        )
+    })?;
+
+    if let Ok(true) = assets_dirpath.try_exists() {
+        let options = fs_extra::dir::CopyOptions {
+            content_only: true,
+            ..Default::default()
+        };
+        fs_extra::dir::copy(&assets_dirpath, &target_assets_dirpath, &options).map_err(|err| {
+            format!(
+                "Could not copy files from {} to {}: {err}",
+                assets_dirpath.to_str().unwrap(),
+                target_assets_dirpath.to_str().unwrap()
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Canonicalizes the closest existing ancestor of a path and appends the
+/// missing components to it, so that paths which do not exist yet can be
+/// compared with canonical ones
+fn canonicalize_missing_path(path: &Path) -> std::io::Result<PathBuf> {
+    let mut missing_components = vec![];
+    let mut ancestor = path;
+    while ancestor.symlink_metadata().is_err() {
+        let (Some(parent), Some(component)) = (ancestor.parent(), ancestor.file_name()) else {
+            return Err(std::io::ErrorKind::NotFound.into());
+        };
+        missing_components.push(component);
+        ancestor = parent;
+    }
+
+    let canonical_ancestor = ancestor.canonicalize()?;
+    Ok(missing_components
+        .into_iter()
+        .rev()
+        .fold(canonical_ancestor, |path, component| path.join(component)))
+}
+
+/// Resolves a path against the assets folder of a service, refusing symlinks
+/// leading out of the folder. The assets folder does not need to exist, as
+/// for services added by hand to the compose file.
+pub fn resolve_asset_path(
+    scene_name: &str,
+    service_id: &str,
+    asset_path: &AssetPath,
+) -> Result<PathBuf, String> {
+    let assets_dirpath = get_service_assets_dirpath(scene_name, service_id);
+    let path = assets_dirpath.join(asset_path);
+
+    let canonical_assets_dirpath = canonicalize_missing_path(&assets_dirpath).map_err(|err| {
+        format!("Cannot read service assets folder for scene {scene_name} and service {service_id}: {err}")
+    })?;
+    let canonical_parent = path
+        .parent()
+        .map(canonicalize_missing_path)
+        .ok_or(format!("Invalid asset path {asset_path}"))?
+        .map_err(|err| format!("Cannot resolve asset path {asset_path}: {err}"))?;
+    if !canonical_parent.starts_with(&canonical_assets_dirpath) {
+        return Err(format!(
+            "Invalid asset path {asset_path}, it leads out of the service assets folder"
+        ));
+    }
+
+    Ok(path)
+}
+
+fn ensure_asset_exists(path: &Path, asset_path: &AssetPath) -> Result<(), String> {
+    match path.symlink_metadata() {
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!("Cannot find asset {asset_path}: {err}")),
+    }
+}
+
+/// Deletes an asset file or folder. Symlinks are deleted, never followed.
+pub fn delete_asset(
+    scene_name: &str,
+    service_id: &str,
+    asset_path: &AssetPath,
+) -> Result<(), String> {
+    let path = resolve_asset_path(scene_name, service_id, asset_path)?;
+    ensure_asset_exists(&path, asset_path)?;
+
+    let result = match path.symlink_metadata().map(|metadata| metadata.is_dir()) {
+        Ok(true) => fs::remove_dir_all(&path),
+        _ => fs::remove_file(&path),
+    };
+    result.map_err(|err| format!("Cannot delete asset {asset_path}: {err}"))
+}
+
+/// Moves an asset to another path of the assets folder, creating the missing
+/// folders on the way
+pub fn move_asset(
+    scene_name: &str,
+    service_id: &str,
+    asset_path: &AssetPath,
+    target: &AssetPath,
+) -> Result<(), String> {
+    let path = resolve_asset_path(scene_name, service_id, asset_path)?;
+    let target_path = resolve_asset_path(scene_name, service_id, target)?;
+    ensure_asset_exists(&path, asset_path)?;
+
+    if target_path.symlink_metadata().is_ok() {
+        return Err("entry_already_exists".to_string());
+    }
+    if target_path.starts_with(&path) {
+        return Err(format!("Cannot move asset {asset_path} into itself"));
+    }
+
+    if let Some(target_base_dir) = target_path.parent() {
+        fs_extra::dir::create_all(target_base_dir, false).map_err(|err| {
+            format!(
+                "Could not create missing folders in {}: {err}",
+                target_path.to_str().unwrap()
+            )
+        })?;
+    }
+
+    fs::rename(&path, &target_path)
+        .map_err(|err| format!("Cannot move asset {asset_path} to {target}: {err}"))
+}
+
+/// Renames an asset within its folder
+pub fn rename_asset(
+    scene_name: &str,
+    service_id: &str,
+    asset_path: &AssetPath,
+    name: &str,
+) -> Result<(), String> {
+    let mut components = Path::new(name).components();
+    let is_file_name = matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    );
+    if !is_file_name {
+        return Err(format!("Invalid asset name {name}"));
+    }
+
+    let target = AssetPath::new(&asset_path.with_file_name(name).to_string_lossy())?;
+
+    move_asset(scene_name, service_id, asset_path, &target)
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AssetUsage {
+    /// Key of the service definition holding the reference
+    pub field: String,
+    /// The reference as written in the compose file
+    pub reference: String,
+    /// Path of the referenced asset, relative to the assets folder and empty
+    /// when the whole folder is referenced
+    pub path: String,
+    /// Path within the container, when the asset is mounted
+    pub target: Option<String>,
+}
+
+/// Gives the path of an asset relative to the assets folder of the service
+/// from a path relative to the scene folder
+fn get_asset_relative_path(path: &str, service_id: &str) -> Option<String> {
+    let rest = path
+        .strip_prefix("./")
+        .unwrap_or(path)
+        .strip_prefix(service_id)?;
+    if !rest.is_empty() && !rest.starts_with('/') {
+        return None;
+    }
+
+    let mut relative_path = PathBuf::new();
+    for component in Path::new(rest).components() {
+        match component {
+            Component::Normal(component) => relative_path.push(component),
+            Component::RootDir | Component::CurDir => {}
+            _ => return None,
+        }
+    }
+
+    Some(relative_path.to_string_lossy().to_string())
+}
+
+/// Lists the volumes, env files and configs of a service that reference its
+/// own assets folder. Named volumes called like the service are not usages.
+pub fn get_service_asset_usages(
+    docker_compose_file: &DockerComposeFile,
+    service_id: &str,
+) -> Vec<AssetUsage> {
+    let Some(service) = docker_compose_file.services.get(service_id) else {
+        return vec![];
+    };
+
+    let mut usages = vec![];
+    let mut add_usage = |field: &str, reference: String, source: &str, target: Option<String>| {
+        if let Some(path) = get_asset_relative_path(source, service_id) {
+            usages.push(AssetUsage {
+                field: field.to_string(),
+                reference,
+                path,
+                target,
+            });
+        }
+    };
+
+    if let Some(Value::Sequence(volumes)) = service.extra.get("volumes") {
+        for volume in volumes {
+            match volume {
+                Value::String(volume) => {
+                    let mut parts = volume.split(':');
+                    if let Some(source) = parts
+                        .next()
+                        .filter(|source| is_relative_volume_source(source))
+                    {
+                        let target = parts.next().map(|target| target.to_string());
+                        add_usage("volumes", volume.to_string(), source, target);
+                    }
+                }
+                Value::Mapping(volume) => {
+                    if volume.get("type").and_then(Value::as_str) != Some("bind") {
+                        continue;
+                    }
+                    if let Some(source) = volume
+                        .get("source")
+                        .and_then(Value::as_str)
+                        .filter(|source| is_relative_bind_source(source))
+                    {
+                        let target = volume
+                            .get("target")
+                            .and_then(Value::as_str)
+                            .map(|target| target.to_string());
+                        add_usage("volumes", source.to_string(), source, target);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let env_files = match service.extra.get("env_file") {
+        Some(Value::String(path)) => vec![path.as_str()],
+        Some(Value::Sequence(env_files)) => env_files
+            .iter()
+            .filter_map(|env_file| match env_file {
+                Value::String(path) => Some(path.as_str()),
+                Value::Mapping(env_file) => env_file.get("path").and_then(Value::as_str),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    };
+    for path in env_files {
+        add_usage("env_file", path.to_string(), path, None);
+    }
+
+    // Service configs point at top-level configs, which hold the file path
+    if let Some(Value::Sequence(configs)) = service.extra.get("configs") {
+        let top_level_configs = docker_compose_file.extra.get("configs");
+        for config in configs {
+            let (source, target) = match config {
+                Value::String(source) => (Some(source.as_str()), None),
+                Value::Mapping(config) => (
+                    config.get("source").and_then(Value::as_str),
+                    config.get("target").and_then(Value::as_str),
+                ),
+                _ => (None, None),
+            };
+            let Some(source) = source else {
+                continue;
+            };
+            let file = top_level_configs
+                .and_then(|configs| configs.get(source))
+                .and_then(|config| config.get("file"))
+                .and_then(Value::as_str);
+            if let Some(file) = file {
+                let target = target
+                    .map(|target| target.to_string())
+                    .unwrap_or(format!("/{source}"));
+                add_usage("configs", source.to_string(), file, Some(target));
+            }
+        }
+    }
+
+    usages
+}
+
+/// Files above this size are not meant to be edited within the app
+const MAX_ASSET_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Number of bytes looked at to tell binary files apart from text files
+const BINARY_DETECTION_LENGTH: usize = 8000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum AssetEncoding {
+    #[default]
+    #[serde(rename = "utf-8")]
+    Utf8,
+    #[serde(rename = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-16be")]
+    Utf16Be,
+    #[serde(rename = "latin1")]
+    Latin1,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AssetFile {
+    /// Missing for binary files
+    pub content: Option<String>,
+    pub binary: bool,
+    pub encoding: AssetEncoding,
+    /// Whether the file starts with a byte order mark, kept when writing back
+    pub bom: bool,
+    pub size: u64,
+}
+
+/// Following symlinks is only allowed while staying in the assets folder
+fn ensure_asset_target_is_confined(
+    scene_name: &str,
+    service_id: &str,
+    path: &Path,
+    asset_path: &AssetPath,
+) -> Result<(), String> {
+    if path.symlink_metadata().is_err() {
+        return Ok(());
+    }
+
+    let canonical_assets_dirpath = get_service_assets_dirpath(scene_name, service_id)
+        .canonicalize()
+        .map_err(|err| format!("Cannot read service assets folder for scene {scene_name} and service {service_id}: {err}"))?;
+    let canonical_path = path
+        .canonicalize()
+        .map_err(|err| format!("Cannot resolve asset path {asset_path}: {err}"))?;
+    if !canonical_path.starts_with(&canonical_assets_dirpath) {
+        return Err(format!(
+            "Invalid asset path {asset_path}, it leads out of the service assets folder"
+        ));
+    }
+
+    Ok(())
+}
+
+fn decode_utf16(bytes: &[u8], to_u16: fn([u8; 2]) -> u16) -> Option<String> {
+    let chunks = bytes.chunks_exact(2);
+    if !chunks.remainder().is_empty() {
+        return None;
+    }
+
+    let units = chunks
+        .map(|chunk| to_u16([chunk[0], chunk[1]]))
+        .collect::<Vec<_>>();
+    String::from_utf16(&units).ok()
+}
+
+/// Decodes the content of a text file, returning `None` for binary files.
+/// Files without a byte order mark that are not valid UTF-8 are read as latin1.
+fn decode_asset_content(bytes: &[u8]) -> Option<(String, AssetEncoding, bool)> {
+    if let Some(bytes) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        let content = String::from_utf8(bytes.to_vec()).ok()?;
+        return Some((content, AssetEncoding::Utf8, true));
+    }
+    if let Some(bytes) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        let content = decode_utf16(bytes, u16::from_le_bytes)?;
+        return Some((content, AssetEncoding::Utf16Le, true));
+    }
+    if let Some(bytes) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        let content = decode_utf16(bytes, u16::from_be_bytes)?;
+        return Some((content, AssetEncoding::Utf16Be, true));
+    }
+
+    let sample = &bytes[..bytes.len().min(BINARY_DETECTION_LENGTH)];
+    if sample.contains(&0) {
+        return None;
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(content) => Some((content.to_string(), AssetEncoding::Utf8, false)),
+        Err(_) => {
+            // Control characters other than whitespace and escapes do not
+            // show up in latin1 text
+            let is_text = sample.iter().all(|byte| {
+                !byte.is_ascii_control() || matches!(byte, b'\t' | b'\n' | b'\r' | 0x0C | 0x1B)
+            });
+            is_text.then(|| {
+                let content = bytes.iter().map(|byte| *byte as char).collect();
+                (content, AssetEncoding::Latin1, false)
+            })
+        }
+    }
+}
+
+fn encode_asset_content(
+    content: &str,
+    encoding: AssetEncoding,
+    bom: bool,
+) -> Result<Vec<u8>, String> {
+    let bytes = match encoding {
+        AssetEncoding::Utf8 => {
+            let bom_bytes: &[u8] = if bom { &[0xEF, 0xBB, 0xBF] } else { &[] };
+            [bom_bytes, content.as_bytes()].concat()
+        }
+        AssetEncoding::Utf16Le | AssetEncoding::Utf16Be => {
+            let to_bytes = match encoding {
+                AssetEncoding::Utf16Le => u16::to_le_bytes,
+                _ => u16::to_be_bytes,
+            };
+            let bom_units = if bom { Some(0xFEFF) } else { None };
+            bom_units
+                .into_iter()
+                .chain(content.encode_utf16())
+                .flat_map(to_bytes)
+                .collect()
+        }
+        AssetEncoding::Latin1 => content
+            .chars()
+            .map(|char| u8::try_from(char as u32))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| {
+                "Content contains characters that cannot be written in latin1".to_string()
+            })?,
+    };
+
+    Ok(bytes)
+}
+
+pub fn read_asset_file(
+    scene_name: &str,
+    service_id: &str,
+    asset_path: &AssetPath,
+) -> Result<AssetFile, String> {
+    let path = resolve_asset_path(scene_name, service_id, asset_path)?;
+    ensure_asset_target_is_confined(scene_name, service_id, &path, asset_path)?;
+
+    let metadata =
+        fs::metadata(&path).map_err(|err| format!("Cannot find asset {asset_path}: {err}"))?;
+    if metadata.is_dir() {
+        return Err(format!("Asset {asset_path} is a folder"));
+    }
+    if metadata.len() > MAX_ASSET_FILE_SIZE {
+        return Err(format!(
+            "Asset {asset_path} is too large to be edited ({} bytes, the limit is {MAX_ASSET_FILE_SIZE} bytes)",
+            metadata.len()
+        ));
+    }
+
+    let bytes = fs::read(&path).map_err(|err| format!("Cannot read asset {asset_path}: {err}"))?;
+    let asset_file = match decode_asset_content(&bytes) {
+        Some((content, encoding, bom)) => AssetFile {
+            content: Some(content),
+            binary: false,
+            encoding,
+            bom,
+            size: metadata.len(),
+        },
+        None => AssetFile {
+            content: None,
+            binary: true,
+            encoding: AssetEncoding::default(),
+            bom: false,
+            size: metadata.len(),
+        },
+    };
+
+    Ok(asset_file)
+}
+
+/// Writes a text asset, creating it and its folders when missing. Existing
+/// files are overwritten in place, as services mounting a single file would
+/// keep reading the previous one if it was replaced.
+pub fn write_asset_file(
+    scene_name: &str,
+    service_id: &str,
+    asset_path: &AssetPath,
+    content: &str,
+    encoding: AssetEncoding,
+    bom: bool,
+) -> Result<(), String> {
+    let path = resolve_asset_path(scene_name, service_id, asset_path)?;
+    ensure_asset_target_is_confined(scene_name, service_id, &path, asset_path)?;
+
+    if path.is_dir() {
+        return Err(format!("Asset {asset_path} is a folder"));
+    }
+
+    let bytes = encode_asset_content(content, encoding, bom)?;
+    if bytes.len() as u64 > MAX_ASSET_FILE_SIZE {
+        return Err(format!(
+            "Content is too large to be written ({} bytes, the limit is {MAX_ASSET_FILE_SIZE} bytes)",
+            bytes.len()
+        ));
+    }
+
+    if let Some(base_dirpath) = path.parent() {
+        fs_extra::dir::create_all(base_dirpath, false).map_err(|err| {
+            format!(
+                "Could not create missing folders in {}: {err}",
+                base_dirpath.to_str().unwrap()
+            )
+        })?;
+    }
+
+    write_file_in_place(&path, &bytes)
+        .map_err(|err| format!("Cannot write asset {asset_path}: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_service(yaml: &str) -> DockerComposeService {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn get_volumes(service: &DockerComposeService) -> Value {
+        service.extra.get("volumes").cloned().unwrap()
+    }
+
+    #[test]
+    fn rewrite_keeps_named_volumes_called_like_the_service() {
+        let mut service = parse_service(
+            "volumes:\n  - db:/var/lib/postgresql/data\n  - db\n  - ./db/init:/docker-entrypoint-initdb.d\n",
+        );
+
+        let rewrites = rewrite_service_asset_paths(&mut service, "db", "db-copy");
+
+        assert_eq!(rewrites.len(), 1);
+        assert_eq!(
+            rewrites[0].previous,
+            "./db/init:/docker-entrypoint-initdb.d"
+        );
+        assert_eq!(
+            get_volumes(&service),
+            serde_yaml::from_str::<Value>(
+                "[db:/var/lib/postgresql/data, db, ./db-copy/init:/docker-entrypoint-initdb.d]"
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn rewrite_keeps_named_volumes_in_long_syntax() {
+        let mut service =
+            parse_service("volumes:\n  - type: volume\n    source: db\n    target: /data\n");
+
+        let rewrites = rewrite_service_asset_paths(&mut service, "db", "db-copy");
+
+        assert!(rewrites.is_empty());
+    }
+
+    #[test]
+    fn rewrite_long_syntax_bind_sources_without_dot_prefix() {
+        let mut service = parse_service(
+            "volumes:\n  - type: bind\n    source: db\n    target: /data\n  - type: bind\n    source: /srv/db\n    target: /srv\n",
+        );
+
+        let rewrites = rewrite_service_asset_paths(&mut service, "db", "db-copy");
+
+        assert_eq!(rewrites.len(), 1);
+        assert_eq!(rewrites[0].previous, "db");
+        assert_eq!(rewrites[0].current, "db-copy");
+    }
+
+    #[test]
+    fn rewrite_short_syntax_volumes() {
+        let mut service = parse_service(
+            "volumes:\n  - ./db/data:/data:ro\n  - ./db:/all\n  - ./dbx:/other\n  - ./other/db:/nested\n",
+        );
+
+        let rewrites = rewrite_service_asset_paths(&mut service, "db", "db-copy");
+
+        assert_eq!(rewrites.len(), 2);
+        assert_eq!(
+            get_volumes(&service),
+            serde_yaml::from_str::<Value>(
+                "[./db-copy/data:/data:ro, ./db-copy:/all, ./dbx:/other, ./other/db:/nested]"
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn rewrite_long_syntax_volumes() {
+        let mut service =
+            parse_service("volumes:\n  - type: bind\n    source: ./db/conf\n    target: /conf\n");
+
+        let rewrites = rewrite_service_asset_paths(&mut service, "db", "db-copy");
+
+        assert_eq!(rewrites.len(), 1);
+        assert_eq!(rewrites[0].field, "volumes");
+        assert_eq!(rewrites[0].current, "./db-copy/conf");
+    }
+
+    #[test]
+    fn rewrite_env_files() {
+        let mut service = parse_service(
+            "env_file:\n  - db/.env\n  - ./db/.env.local\n  - path: db/.env.prod\n    required: false\n  - other/.env\n",
+        );
+
+        let rewrites = rewrite_service_asset_paths(&mut service, "db", "db-copy");
+
+        assert_eq!(
+            rewrites
+                .iter()
+                .map(|rewrite| (rewrite.field.as_str(), rewrite.current.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("env_file", "db-copy/.env"),
+                ("env_file", "./db-copy/.env.local"),
+                ("env_file", "db-copy/.env.prod"),
+            ]
+        );
+
+        let mut service = parse_service("env_file: db/.env\n");
+        rewrite_service_asset_paths(&mut service, "db", "db-copy");
+        assert_eq!(
+            service.extra.get("env_file"),
+            Some(&Value::from("db-copy/.env"))
+        );
+    }
+
+    #[test]
+    fn rewrite_build_contexts() {
+        let mut service = parse_service("build: db\n");
+        let rewrites = rewrite_service_asset_paths(&mut service, "db", "db-copy");
+        assert_eq!(rewrites.len(), 1);
+        assert_eq!(service.extra.get("build"), Some(&Value::from("db-copy")));
+
+        let mut service =
+            parse_service("build:\n  context: ./db/image\n  dockerfile: Dockerfile.dev\n");
+        let rewrites = rewrite_service_asset_paths(&mut service, "db", "db-copy");
+        assert_eq!(rewrites.len(), 1);
+        assert_eq!(rewrites[0].field, "build");
+        assert_eq!(rewrites[0].current, "./db-copy/image");
+    }
+
+    #[test]
+    fn usages_skip_named_volumes_called_like_the_service() {
+        let docker_compose_file = serde_yaml::from_str::<DockerComposeFile>(
+            "services:\n  data:\n    volumes:\n      - data:/var/lib/data\n      - ./data/conf:/etc/conf\n      - type: volume\n        source: data\n        target: /other\n      - type: bind\n        source: data/init\n        target: /init\n    env_file: data/.env\n",
+        )
+        .unwrap();
+
+        let usages = get_service_asset_usages(&docker_compose_file, "data");
+
+        assert_eq!(
+            usages
+                .iter()
+                .map(|usage| (usage.field.as_str(), usage.path.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("volumes", "conf"),
+                ("volumes", "init"),
+                ("env_file", ".env")
+            ]
+        );
+    }
+
+    #[test]
+    fn canonicalize_missing_path_keeps_missing_components() {
+        let dirpath = std::env::temp_dir().canonicalize().unwrap();
+        let path = dirpath.join("dcompose-workbench-missing").join("assets");
+
+        assert_eq!(canonicalize_missing_path(&path).unwrap(), path);
+    }
+}
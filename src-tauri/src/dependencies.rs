@@ -1,21 +1,63 @@
-use crate::docker::{self};
+use tauri::State;
+
+use crate::{
+    docker::{self},
+    history,
+    paths::{SceneName, ServiceId},
+    state::AppState,
+};
 
 #[tauri::command(async)]
-pub fn create_dependency(scene_name: &str, source: &str, target: &str) -> Result<(), String> {
-    docker::add_dependency(scene_name, target, source)
+pub fn create_dependency(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    source: ServiceId,
+    target: ServiceId,
+    compose_file_hash: Option<String>,
+) -> Result<(), String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    docker::ensure_docker_compose_file_is_unchanged(&scene_name, compose_file_hash.as_deref())?;
+    history::record_scene_edit(
+        &scene_name,
+        &format!("Add dependency of {target} on {source}"),
+        &[history::compose_file_path(&scene_name)],
+        || docker::add_dependency(&scene_name, &target, &source),
+    )
 }
 
 #[tauri::command(async)]
-pub fn delete_dependency(scene_name: &str, source: &str, target: &str) -> Result<(), String> {
-    docker::remove_dependency(scene_name, target, source)
+pub fn delete_dependency(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    source: ServiceId,
+    target: ServiceId,
+    compose_file_hash: Option<String>,
+) -> Result<(), String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    docker::ensure_docker_compose_file_is_unchanged(&scene_name, compose_file_hash.as_deref())?;
+    history::record_scene_edit(
+        &scene_name,
+        &format!("Remove dependency of {target} on {source}"),
+        &[history::compose_file_path(&scene_name)],
+        || docker::remove_dependency(&scene_name, &target, &source),
+    )
 }
 
 #[tauri::command(async)]
 pub fn set_dependency_condition(
-    scene_name: &str,
-    source: &str,
-    target: &str,
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    source: ServiceId,
+    target: ServiceId,
     condition: &str,
+    compose_file_hash: Option<String>,
 ) -> Result<(), String> {
-    docker::set_depends_on_condition(scene_name, target, source, condition)
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    docker::ensure_docker_compose_file_is_unchanged(&scene_name, compose_file_hash.as_deref())?;
+    history::record_scene_edit(
+        &scene_name,
+        &format!("Set condition of dependency of {target} on {source}"),
+        &[history::compose_file_path(&scene_name)],
+        || docker::set_depends_on_condition(&scene_name, &target, &source, condition),
+    )
 }
@@ -1,26 +1,33 @@
 use bollard::{
-    container::{InspectContainerOptions, LogOutput, LogsOptions},
-    secret::{ContainerState, ContainerStateStatusEnum, HealthStatusEnum},
+    container::{InspectContainerOptions, ListContainersOptions},
+    models::EventMessage,
+    secret::{ContainerState, ContainerStateStatusEnum, Health, HealthStatusEnum},
+    system::EventsOptions,
     Docker,
 };
 use chrono::DateTime;
-use futures::StreamExt;
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt,
+};
 use path_absolutize::Absolutize;
 use serde::{Deserialize, Serialize};
 use serde_yaml::Value;
 use std::{
-    collections::HashMap,
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
     fs,
+    hash::{Hash, Hasher},
     path::PathBuf,
     process::{Command, Stdio},
+    sync,
     time::Duration,
 };
 use tauri::{AppHandle, Manager, State};
 use tokio::{spawn, task::JoinHandle, time::sleep};
 
 use crate::{
-    state::{AppState, ServiceKey},
-    utils::{get_config_dirpath, get_formatted_date},
+    state::AppState,
+    utils::{get_config_dirpath, get_formatted_date, write_file_atomically},
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -57,7 +64,7 @@ pub struct DockerComposeService {
     pub depends_on: Option<HashMap<String, DockerComposeDependsOn>>,
 
     #[serde(flatten)]
-    extra: HashMap<String, Value>,
+    pub extra: HashMap<String, Value>,
 }
 
 fn is_none_or_empty(depends_on: &Option<HashMap<String, DockerComposeDependsOn>>) -> bool {
@@ -74,7 +81,12 @@ pub struct DockerComposeFile {
     pub include: Option<Vec<DockerComposeIncludeEnum>>,
 
     #[serde(flatten)]
-    extra: HashMap<String, Value>,
+    pub extra: HashMap<String, Value>,
+
+    /// Hash of the file content this was read from, to detect changes made
+    /// outside of the app before writing it back
+    #[serde(skip)]
+    pub source_hash: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -101,7 +113,7 @@ pub enum DockerComposeIncludeStringOrList {
     List(Vec<String>),
 }
 
-fn get_docker_compose_dirpath(scene_name: &str) -> PathBuf {
+pub fn get_docker_compose_dirpath(scene_name: &str) -> PathBuf {
     get_config_dirpath().join("scenes").join(scene_name)
 }
 
@@ -111,19 +123,136 @@ pub fn get_docker_compose_file(scene_name: &str) -> Result<DockerComposeFile, St
     let docker_compose_file_string = fs::read_to_string(&docker_compose_filepath)
         .map_err(|err| format!("Cannot find file {:?}: {}", docker_compose_filepath, err))?;
 
-    serde_yaml::from_str::<DockerComposeFile>(&docker_compose_file_string)
-        .map_err(|err| format!("Cannot parse docker-compose.yml: {}", err))
+    let mut docker_compose_file =
+        serde_yaml::from_str::<DockerComposeFile>(&docker_compose_file_string)
+            .map_err(|err| format!("Cannot parse docker-compose.yml: {}", err))?;
+    docker_compose_file.source_hash = Some(get_content_hash(&docker_compose_file_string));
+
+    Ok(docker_compose_file)
 }
 
+pub fn get_content_hash(content: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Hash of the current content of the compose file of a scene, missing when
+/// the file cannot be read
+pub fn get_docker_compose_file_hash(scene_name: &str) -> Option<String> {
+    fs::read_to_string(get_docker_compose_dirpath(scene_name).join("docker-compose.yml"))
+        .ok()
+        .map(|content| get_content_hash(&content))
+}
+
+/// Number of consecutive versions written by the app that are remembered per
+/// compose file
+const MAX_WRITTEN_VERSIONS: usize = 100;
+
+/// Hashes of the consecutive versions of each compose file written by the
+/// app, the last one being the current version as long as no other program
+/// changed the file since
+static WRITTEN_VERSIONS: sync::Mutex<BTreeMap<String, Vec<String>>> =
+    sync::Mutex::new(BTreeMap::new());
+
+/// Records a version of the compose file written by the app, along with the
+/// version it replaced when known
+pub fn record_docker_compose_file_write(
+    scene_name: &str,
+    previous_hash: Option<String>,
+    hash: String,
+) {
+    let mut written_versions = WRITTEN_VERSIONS.lock().unwrap();
+    let versions = written_versions.entry(scene_name.to_string()).or_default();
+
+    // A version the app did not write starts a new chain
+    if previous_hash.is_none() || versions.last() != previous_hash.as_ref() {
+        versions.clear();
+        versions.extend(previous_hash);
+    }
+    versions.push(hash);
+    if versions.len() > MAX_WRITTEN_VERSIONS {
+        versions.drain(..versions.len() - MAX_WRITTEN_VERSIONS);
+    }
+}
+
+/// Whether the compose file content with this hash is the last one written by
+/// the app, as opposed to changes made by other programs
+pub fn is_docker_compose_file_written_by_app(scene_name: &str, hash: &str) -> bool {
+    WRITTEN_VERSIONS
+        .lock()
+        .unwrap()
+        .get(scene_name)
+        .and_then(|versions| versions.last())
+        .is_some_and(|last_hash| last_hash == hash)
+}
+
+/// Refuses edits based on a version of the compose file loaded by the
+/// frontend when the file was changed outside of the app since. Changes made
+/// by the app itself in the meantime are fine, they do not need a reload.
+pub fn ensure_docker_compose_file_is_unchanged(
+    scene_name: &str,
+    loaded_hash: Option<&str>,
+) -> Result<(), String> {
+    let Some(loaded_hash) = loaded_hash else {
+        return Ok(());
+    };
+
+    let current_hash = get_docker_compose_file_hash(scene_name);
+    if current_hash.as_deref() == Some(loaded_hash) {
+        return Ok(());
+    }
+
+    let is_written_by_app = WRITTEN_VERSIONS
+        .lock()
+        .unwrap()
+        .get(scene_name)
+        .is_some_and(|versions| {
+            versions.last() == current_hash.as_ref()
+                && versions.iter().any(|hash| hash == loaded_hash)
+        });
+    match is_written_by_app {
+        true => Ok(()),
+        false => Err(format!(
+            "docker-compose.yml of scene {scene_name} was modified outside of the app since it was loaded, reload the scene before editing it"
+        )),
+    }
+}
+
+/// Writes the compose file of a scene atomically, refusing to overwrite it
+/// when it was modified since it was read. Callers must hold the scene write
+/// lock between reading and writing the file.
 pub fn write_docker_compose_file(
     scene_name: &str,
-    docker_compose: &DockerComposeFile,
+    docker_compose: &mut DockerComposeFile,
 ) -> Result<(), String> {
     let docker_compose_filepath = get_docker_compose_dirpath(scene_name).join("docker-compose.yml");
 
+    if let Some(source_hash) = &docker_compose.source_hash {
+        let current_content = fs::read_to_string(&docker_compose_filepath)
+            .map_err(|err| format!("Cannot find file {:?}: {}", docker_compose_filepath, err))?;
+        if &get_content_hash(&current_content) != source_hash {
+            return Err(format!(
+                "docker-compose.yml of scene {scene_name} was modified outside of the app, reload the scene before editing it"
+            ));
+        }
+    }
+
     let docker_compose_stringified = serde_yaml::to_string(docker_compose).unwrap();
-    fs::write(&docker_compose_filepath, docker_compose_stringified)
-        .map_err(|err| format!("Cannot write file {:?}: {}", docker_compose_filepath, err))
+    write_file_atomically(
+        &docker_compose_filepath,
+        docker_compose_stringified.as_bytes(),
+    )
+    .map_err(|err| format!("Cannot write file {:?}: {}", docker_compose_filepath, err))?;
+
+    let hash = get_content_hash(&docker_compose_stringified);
+    record_docker_compose_file_write(
+        scene_name,
+        docker_compose.source_hash.replace(hash.clone()),
+        hash,
+    );
+
+    Ok(())
 }
 
 pub fn run_docker_compose_up(scene_name: &str, service_id: Option<&str>) -> Result<(), String> {
@@ -161,11 +290,18 @@ pub fn run_docker_compose_up(scene_name: &str, service_id: Option<&str>) -> Resu
     }
 }
 
-pub fn run_docker_compose_down(scene_name: &str, service_id: Option<&str>) -> Result<(), String> {
-    let args: Vec<&str> = match service_id {
+pub fn run_docker_compose_down(
+    scene_name: &str,
+    service_id: Option<&str>,
+    remove_volumes: bool,
+) -> Result<(), String> {
+    let mut args: Vec<&str> = match service_id {
         None => ["down"].to_vec(),
         Some(x) => ["down", x].to_vec(),
     };
+    if remove_volumes {
+        args.push("--volumes");
+    }
 
     let service_id_format_string = service_id
         .map(|service_id| format!(" {service_id}"))
@@ -204,7 +340,9 @@ struct DockerComposePsResponse {
     name: String,
 }
 
-fn get_container_names_from_services(scene_name: &str) -> Result<HashMap<String, String>, String> {
+pub fn get_container_names_from_services(
+    scene_name: &str,
+) -> Result<HashMap<String, String>, String> {
     let output = Command::new("docker-compose")
         .current_dir(get_docker_compose_dirpath(scene_name))
         .args(["ps", "--format", "json"])
@@ -237,186 +375,142 @@ fn get_container_names_from_services(scene_name: &str) -> Result<HashMap<String,
     }
 }
 
-#[derive(Serialize, Clone)]
-enum LogType {
-    #[serde(rename = "stderr")]
-    StdErr,
-    #[serde(rename = "stdout")]
-    StdOut,
+/// Mirrors the normalization docker compose applies to project names, which is
+/// the value it stores in the `com.docker.compose.project` container label
+pub fn get_compose_project_name(scene_name: &str) -> String {
+    scene_name
+        .to_lowercase()
+        .chars()
+        .filter(|char| char.is_ascii_alphanumeric() || *char == '_' || *char == '-')
+        .skip_while(|char| !char.is_ascii_alphanumeric())
+        .collect()
 }
 
-#[derive(Serialize, Clone)]
-struct ServiceLogEventPayload {
-    text: String,
-    timestamp: String,
-    #[serde(rename = "type")]
-    type_name: LogType,
-    clear: bool,
+#[derive(Debug, Clone)]
+pub struct DockerComposeContainer {
+    pub id: String,
+    pub name: String,
+    pub service_id: String,
+    pub replica: u32,
 }
 
-pub async fn start_emitting_service_logs(
-    app: &AppHandle,
+pub async fn get_scene_containers(
+    docker: &Docker,
     scene_name: &str,
-    service_id: &str,
-) -> Result<(), String> {
-    let docker = Docker::connect_with_socket_defaults()
-        .map_err(|error| format!("Cannot connect to docker socket: {}", error))?;
-
-    let thread_app = app.to_owned();
-    let thread_scene_name = scene_name.to_string();
-    let thread_service_id = service_id.to_string();
-    let logs_handle = spawn(async move {
-        let service_log_event_name = format!("{thread_scene_name}-{thread_service_id}-log-event");
-
-        let container_name = loop {
-            match get_container_names_from_services(&thread_scene_name) {
-                Err(err) => {
-                    thread_app
-                        .emit_all(
-                            service_log_event_name.as_ref(),
-                            ServiceLogEventPayload {
-                                text: format!("Error getting containers: {}", err),
-                                timestamp: get_formatted_date(None),
-                                clear: true,
-                                type_name: LogType::StdErr,
-                            },
-                        )
-                        .unwrap();
-                }
-                Ok(service_name_map) => {
-                    let container_name = service_name_map.get(&thread_service_id);
+) -> Result<Vec<DockerComposeContainer>, String> {
+    let project_name = get_compose_project_name(scene_name);
+
+    let containers = docker
+        .list_containers(Some(ListContainersOptions::<String> {
+            all: true,
+            filters: HashMap::from([(
+                "label".to_string(),
+                vec![format!("com.docker.compose.project={project_name}")],
+            )]),
+            ..Default::default()
+        }))
+        .await
+        .map_err(|err| format!("Cannot list containers for scene {scene_name}: {err}"))?;
+
+    let mut scene_containers = containers
+        .into_iter()
+        .filter_map(|container| {
+            let labels = container.labels.unwrap_or_default();
+            let service_id = labels.get("com.docker.compose.service")?.to_string();
+            let replica = labels
+                .get("com.docker.compose.container-number")
+                .and_then(|replica| replica.parse().ok())
+                .unwrap_or(1);
+            let name = container
+                .names
+                .and_then(|names| names.first().cloned())
+                .map(|name| name.trim_start_matches('/').to_string())?;
+
+            Some(DockerComposeContainer {
+                id: container.id.unwrap_or_default(),
+                name,
+                service_id,
+                replica,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    scene_containers.sort_by(|a, b| {
+        a.service_id
+            .cmp(&b.service_id)
+            .then(a.replica.cmp(&b.replica))
+    });
 
-                    match container_name {
-                        Some(container_name) => break container_name.to_string(),
-                        None => {
-                            thread_app
-                                .emit_all(
-                                    service_log_event_name.as_ref(),
-                                    ServiceLogEventPayload {
-                                        text: "Container does not exist for this service..."
-                                            .to_string(),
-                                        timestamp: get_formatted_date(None),
-                                        clear: true,
-                                        type_name: LogType::StdErr,
-                                    },
-                                )
-                                .unwrap();
-                        }
-                    }
+    Ok(scene_containers)
+}
 
-                    sleep(Duration::from_secs(1)).await;
-                }
-            }
-        };
+/// Wait before following docker events again once their stream failed
+const EVENTS_RETRY_DELAY: Duration = Duration::from_secs(5);
 
-        let mut logs_stream = docker.logs::<String>(
-            &container_name,
-            Some(LogsOptions::<String> {
-                follow: true,
-                tail: "all".to_string(),
-                stderr: true,
-                stdout: true,
-                timestamps: true,
-                ..Default::default()
-            }),
-        );
-
-        while let Some(log) = logs_stream.next().await {
-            match log {
-                Ok(log) => {
-                    let log_string = log.to_string();
-                    let (timestamp, text) = match log_string.split_once(' ') {
-                        None => ("".to_string(), log_string),
-                        Some((timestamp, text)) => (
-                            get_formatted_date(
-                                DateTime::parse_from_rfc3339(timestamp)
-                                    .map(|date| date.into())
-                                    .ok(),
-                            ),
-                            text.to_string(),
-                        ),
-                    };
+/// Streams the containers started in a scene, or in a single service of it,
+/// so that tasks following containers notice the started, restarted,
+/// recreated and scaled ones. Docker events are followed again after a while
+/// when their stream fails.
+pub fn get_started_containers(
+    docker: &Docker,
+    scene_name: &str,
+    service_id: Option<&str>,
+) -> BoxStream<'static, DockerComposeContainer> {
+    let mut labels = vec![format!(
+        "com.docker.compose.project={}",
+        get_compose_project_name(scene_name)
+    )];
+    if let Some(service_id) = service_id {
+        labels.push(format!("com.docker.compose.service={service_id}"));
+    }
+    let options = EventsOptions::<String> {
+        filters: HashMap::from([
+            ("type".to_string(), vec!["container".to_string()]),
+            ("event".to_string(), vec!["start".to_string()]),
+            ("label".to_string(), labels),
+        ]),
+        ..Default::default()
+    };
 
-                    match log {
-                        LogOutput::StdOut { message: _ } => {
-                            thread_app
-                                .emit_all(
-                                    service_log_event_name.as_ref(),
-                                    ServiceLogEventPayload {
-                                        text,
-                                        timestamp,
-                                        clear: false,
-                                        type_name: LogType::StdOut,
-                                    },
-                                )
-                                .unwrap();
-                        }
-                        LogOutput::StdErr { message: _ } => {
-                            thread_app
-                                .emit_all(
-                                    service_log_event_name.as_ref(),
-                                    ServiceLogEventPayload {
-                                        text,
-                                        timestamp,
-                                        clear: false,
-                                        type_name: LogType::StdErr,
-                                    },
-                                )
-                                .unwrap();
-                        }
-                        _ => {}
+    let docker = docker.clone();
+    stream::unfold(None, move |events| {
+        let docker = docker.clone();
+        let options = options.clone();
+        async move {
+            let mut events = match events {
+                Some(events) => events,
+                None => docker.events(Some(options.clone())).boxed(),
+            };
+            loop {
+                match events.next().await {
+                    Some(Ok(event)) => match get_event_container(&event) {
+                        Some(container) => return Some((container, Some(events))),
+                        None => continue,
+                    },
+                    _ => {
+                        sleep(EVENTS_RETRY_DELAY).await;
+                        events = docker.events(Some(options.clone())).boxed();
                     }
                 }
-                Err(error) => {
-                    thread_app
-                        .emit_all(
-                            service_log_event_name.as_ref(),
-                            ServiceLogEventPayload {
-                                text: format!("Logs stream interrupted: {}", error),
-                                timestamp: get_formatted_date(None),
-                                clear: true,
-                                type_name: LogType::StdErr,
-                            },
-                        )
-                        .unwrap();
-                }
             }
         }
-    });
-
-    let state = app.state::<AppState>();
-    state.service_log_handles.lock().await.insert(
-        ServiceKey {
-            scene_name: scene_name.to_string(),
-            service_id: service_id.to_string(),
-        },
-        logs_handle,
-    );
-
-    Ok(())
+    })
+    .boxed()
 }
 
-pub async fn stop_emitting_service_logs(
-    state: State<'_, AppState>,
-    scene_name: &str,
-    service_id: &str,
-) -> Result<(), String> {
-    let service_log_handles = state.service_log_handles.lock().await;
-    let log_handle = service_log_handles.get(&ServiceKey {
-        scene_name: scene_name.to_string(),
-        service_id: service_id.to_string(),
-    });
+fn get_event_container(event: &EventMessage) -> Option<DockerComposeContainer> {
+    let actor = event.actor.as_ref()?;
+    let attributes = actor.attributes.as_ref()?;
 
-    match log_handle {
-        Some(log_handle) => {
-            log_handle.abort();
-            Ok(())
-        }
-        None => Err(format!(
-            "Could not find the log emitting process for scene {} and service {}",
-            scene_name, service_id
-        )),
-    }
+    Some(DockerComposeContainer {
+        id: actor.id.clone()?,
+        name: attributes.get("name")?.to_string(),
+        service_id: attributes.get("com.docker.compose.service")?.to_string(),
+        replica: attributes
+            .get("com.docker.compose.container-number")
+            .and_then(|replica| replica.parse().ok())
+            .unwrap_or(1),
+    })
 }
 
 #[derive(Serialize, Clone)]
@@ -435,6 +529,85 @@ enum ServiceStatus {
 struct ServiceStatusEventPayload {
     status: ServiceStatus,
     message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    health: Option<ServiceHealth>,
+}
+
+/// Number of healthcheck results sent along with the status, docker itself
+/// only keeps the last 5 of them
+const HEALTH_LOG_LIMIT: usize = 5;
+
+#[derive(Serialize, Clone)]
+pub struct ServiceHealthcheckResult {
+    pub start: Option<String>,
+    pub end: Option<String>,
+    #[serde(rename = "exitCode")]
+    pub exit_code: Option<i64>,
+    pub output: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ServiceHealth {
+    pub status: Option<String>,
+    #[serde(rename = "failingStreak")]
+    pub failing_streak: i64,
+    pub log: Vec<ServiceHealthcheckResult>,
+}
+
+fn get_service_health(health: &Health, limit: usize) -> ServiceHealth {
+    let format_date = |date: &Option<String>| {
+        date.as_ref().map(|date| {
+            get_formatted_date(
+                DateTime::parse_from_rfc3339(date)
+                    .map(|date| date.into())
+                    .ok(),
+            )
+        })
+    };
+
+    let log = health.log.clone().unwrap_or_default();
+    ServiceHealth {
+        status: health.status.map(|status| status.to_string()),
+        failing_streak: health.failing_streak.unwrap_or_default(),
+        log: log
+            .iter()
+            .skip(log.len().saturating_sub(limit))
+            .map(|result| ServiceHealthcheckResult {
+                start: format_date(&result.start),
+                end: format_date(&result.end),
+                exit_code: result.exit_code,
+                output: result.output.clone(),
+            })
+            .collect(),
+    }
+}
+
+pub async fn get_service_health_history(
+    scene_name: &str,
+    service_id: &str,
+    limit: Option<usize>,
+) -> Result<Option<ServiceHealth>, String> {
+    let docker = Docker::connect_with_socket_defaults()
+        .map_err(|error| format!("Cannot connect to docker socket: {}", error))?;
+
+    let container_name = get_container_names_from_services(scene_name)?
+        .remove(service_id)
+        .ok_or(format!(
+            "Container does not exist for service {service_id} in scene {scene_name}"
+        ))?;
+
+    let container = docker
+        .inspect_container(
+            &container_name,
+            Some(InspectContainerOptions { size: false }),
+        )
+        .await
+        .map_err(|error| format!("Error while retrieving service health: {}", error))?;
+
+    Ok(container
+        .state
+        .and_then(|state| state.health)
+        .map(|health| get_service_health(&health, limit.unwrap_or(HEALTH_LOG_LIMIT))))
 }
 
 pub async fn start_emitting_scene_status(app: &AppHandle, scene_name: &str) -> Result<(), String> {
@@ -446,11 +619,36 @@ pub async fn start_emitting_scene_status(app: &AppHandle, scene_name: &str) -> R
     let service_status_handles = emit_services_status(app, scene_name, &docker, &service_ids);
 
     let state = app.state::<AppState>();
-    state
+    if let Some(previous_handles) = state
+        .service_status_handles
+        .lock()
+        .await
+        .insert(scene_name.to_string(), service_status_handles)
+    {
+        for previous_handle in previous_handles {
+            previous_handle.abort();
+        }
+    }
+
+    Ok(())
+}
+
+/// Restarts the status tasks of a scene, if any, so that they follow the
+/// current list of services
+pub async fn restart_emitting_scene_status(
+    app: &AppHandle,
+    scene_name: &str,
+) -> Result<(), String> {
+    let is_emitting = app
+        .state::<AppState>()
         .service_status_handles
         .lock()
         .await
-        .insert(scene_name.to_string(), service_status_handles);
+        .contains_key(scene_name);
+
+    if is_emitting {
+        start_emitting_scene_status(app, scene_name).await?;
+    }
 
     Ok(())
 }
@@ -533,6 +731,7 @@ fn emit_services_status(
                                     ServiceStatusEventPayload {
                                         status: ServiceStatus::Error,
                                         message: Some(format!("Error getting containers: {}", err)),
+                                        health: None,
                                     },
                                 )
                                 .unwrap();
@@ -551,6 +750,7 @@ fn emit_services_status(
                                                 message: Some(
                                                     "Status: unexisting container".to_string(),
                                                 ),
+                                                health: None,
                                             },
                                         )
                                         .unwrap();
@@ -591,6 +791,10 @@ fn emit_services_status(
                                                     )
                                                     .unwrap(),
                                                     message: Some(format!("Status: {}", status)),
+                                                    health: Some(get_service_health(
+                                                        health,
+                                                        HEALTH_LOG_LIMIT,
+                                                    )),
                                                 },
                                             )
                                             .unwrap();
@@ -615,6 +819,7 @@ fn emit_services_status(
                                     ServiceStatusEventPayload {
                                         status: ServiceStatus::Paused,
                                         message: None,
+                                        health: None,
                                     },
                                 )
                                 .unwrap();
@@ -630,6 +835,7 @@ fn emit_services_status(
                                         "Error while retrieving service status: {}",
                                         error
                                     )),
+                                    health: None,
                                 },
                             )
                             .unwrap();
@@ -664,6 +870,7 @@ fn emit_service_status_by_status(
                             .map(|exit_code| exit_code.to_string())
                             .unwrap_or("Unknown".to_string())
                     )),
+                    health: None,
                 },
             )
             .unwrap();
@@ -674,6 +881,7 @@ fn emit_service_status_by_status(
                 ServiceStatusEventPayload {
                     status: get_service_status_from_container_status(status),
                     message: Some(format!("Status: {}", status)),
+                    health: None,
                 },
             )
             .unwrap();
@@ -684,6 +892,7 @@ fn emit_service_status_by_status(
                 ServiceStatusEventPayload {
                     status: ServiceStatus::Paused,
                     message: None,
+                    health: None,
                 },
             )
             .unwrap();
@@ -719,8 +928,7 @@ pub async fn stop_emitting_scene_status(
     state: State<'_, AppState>,
     scene_name: &str,
 ) -> Result<(), String> {
-    let service_status_handles = state.service_status_handles.lock().await;
-    let status_handles = service_status_handles.get(scene_name);
+    let status_handles = state.service_status_handles.lock().await.remove(scene_name);
 
     if let Some(status_handles) = status_handles {
         for status_handle in status_handles {
@@ -754,7 +962,7 @@ pub fn add_dependency(scene_name: &str, service_id: &str, depends_on: &str) -> R
         },
     );
 
-    write_docker_compose_file(scene_name, &docker_compose)
+    write_docker_compose_file(scene_name, &mut docker_compose)
 }
 
 pub fn remove_dependency(
@@ -776,7 +984,7 @@ pub fn remove_dependency(
         Some(x) => match x.remove(depends_on) {
             None => {}
             Some(_) => {
-                write_docker_compose_file(scene_name, &docker_compose)?;
+                write_docker_compose_file(scene_name, &mut docker_compose)?;
             }
         },
     }
@@ -808,5 +1016,5 @@ pub fn set_depends_on_condition(
         ))?
         .condition = condition.to_string();
 
-    write_docker_compose_file(scene_name, &docker_compose)
+    write_docker_compose_file(scene_name, &mut docker_compose)
 }
@@ -0,0 +1,488 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets, docker,
+    paths::AssetPath,
+    state::SceneWriteLocks,
+    utils::{get_config_dirpath, write_file_atomically},
+    watchers,
+};
+
+/// Number of edits that can be undone, per scene
+const MAX_HISTORY_LENGTH: usize = 50;
+
+const HISTORY_FILENAME: &str = "history.json";
+
+/// Path within the scenes folder of a file or folder an edit changes. Its
+/// state before the edit is kept in the history entry folder under the same
+/// relative path.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct HistoryPath {
+    path: PathBuf,
+    existed: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistoryEntry {
+    id: String,
+    pub label: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    paths: Vec<HistoryPath>,
+}
+
+impl HistoryEntry {
+    /// Scenes whose files the entry restores, which must be locked while it
+    /// is applied
+    fn get_scene_names(&self) -> Vec<String> {
+        let mut scene_names = vec![];
+        for history_path in &self.paths {
+            if let Some(scene_name) = history_path.path.iter().next() {
+                let scene_name = scene_name.to_string_lossy().to_string();
+                if !scene_names.contains(&scene_name) {
+                    scene_names.push(scene_name);
+                }
+            }
+        }
+        scene_names
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct SceneHistory {
+    pub undo: Vec<HistoryEntry>,
+    pub redo: Vec<HistoryEntry>,
+}
+
+/// Paths of the files and folders changed by the edits, relative to the
+/// scenes folder
+pub fn scene_path(scene_name: &str) -> PathBuf {
+    PathBuf::from(scene_name)
+}
+
+pub fn compose_file_path(scene_name: &str) -> PathBuf {
+    scene_path(scene_name).join("docker-compose.yml")
+}
+
+pub fn service_assets_path(scene_name: &str, service_id: &str) -> PathBuf {
+    scene_path(scene_name).join(service_id)
+}
+
+/// Fails for paths leading out of the assets folder through symlinks, which
+/// must not be copied nor restored
+pub fn asset_path(
+    scene_name: &str,
+    service_id: &str,
+    asset_path: &AssetPath,
+) -> Result<PathBuf, String> {
+    assets::resolve_asset_path(scene_name, service_id, asset_path)?;
+    Ok(service_assets_path(scene_name, service_id).join(asset_path))
+}
+
+fn get_scenes_dirpath() -> PathBuf {
+    get_config_dirpath().join("scenes")
+}
+
+fn get_history_dirpath(scene_name: &str) -> PathBuf {
+    get_config_dirpath().join("history").join(scene_name)
+}
+
+fn read_scene_history(scene_name: &str) -> Result<SceneHistory, String> {
+    let history_filepath = get_history_dirpath(scene_name).join(HISTORY_FILENAME);
+    if !history_filepath.is_file() {
+        return Ok(SceneHistory::default());
+    }
+
+    let history = fs::read_to_string(&history_filepath)
+        .map_err(|err| format!("Cannot read history of scene {scene_name}: {err}"))?;
+    serde_json::from_str(&history)
+        .map_err(|err| format!("Cannot parse history of scene {scene_name}: {err}"))
+}
+
+fn write_scene_history(scene_name: &str, history: &SceneHistory) -> Result<(), String> {
+    let history_dirpath = get_history_dirpath(scene_name);
+    fs_extra::dir::create_all(&history_dirpath, false)
+        .map_err(|err| format!("Cannot create history folder of scene {scene_name}: {err}"))?;
+
+    let history = serde_json::to_string(history).unwrap();
+    write_file_atomically(&history_dirpath.join(HISTORY_FILENAME), history.as_bytes())
+        .map_err(|err| format!("Cannot write history of scene {scene_name}: {err}"))
+}
+
+fn remove_path(path: &Path) -> Result<(), String> {
+    let Ok(metadata) = path.symlink_metadata() else {
+        return Ok(());
+    };
+
+    match metadata.is_dir() {
+        true => fs::remove_dir_all(path),
+        false => fs::remove_file(path),
+    }
+    .map_err(|err| format!("Cannot remove {}: {err}", path.display()))
+}
+
+fn move_path(path: &Path, target_path: &Path) -> Result<(), String> {
+    if let Some(target_dirpath) = target_path.parent() {
+        fs_extra::dir::create_all(target_dirpath, false)
+            .map_err(|err| format!("Cannot create folder {}: {err}", target_dirpath.display()))?;
+    }
+
+    fs::rename(path, target_path).map_err(|err| {
+        format!(
+            "Cannot move {} to {}: {err}",
+            path.display(),
+            target_path.display()
+        )
+    })
+}
+
+fn copy_path(path: &Path, target_path: &Path) -> Result<(), String> {
+    let target_dirpath = target_path.parent().unwrap();
+    fs_extra::dir::create_all(target_dirpath, false)
+        .map_err(|err| format!("Cannot create folder {}: {err}", target_dirpath.display()))?;
+
+    if path.is_dir() {
+        fs_extra::dir::copy(path, target_dirpath, &fs_extra::dir::CopyOptions::new())
+            .map(|_| ())
+            .map_err(|err| format!("Cannot copy {}: {err}", path.display()))
+    } else {
+        fs::copy(path, target_path)
+            .map(|_| ())
+            .map_err(|err| format!("Cannot copy {}: {err}", path.display()))
+    }
+}
+
+fn create_entry_id() -> String {
+    Utc::now()
+        .timestamp_nanos_opt()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Keeps a copy of the paths as they are before an edit. Removed paths are
+/// moved into the entry rather than copied, which deletes them.
+fn take_snapshot(
+    scene_name: &str,
+    label: &str,
+    paths: &[PathBuf],
+    removed_paths: &[PathBuf],
+) -> Result<HistoryEntry, String> {
+    let id = create_entry_id();
+    let entry_dirpath = get_history_dirpath(scene_name).join(&id);
+
+    let mut history_paths = vec![];
+    for path in paths.iter().chain(removed_paths) {
+        let source_path = get_scenes_dirpath().join(path);
+        let existed = source_path.symlink_metadata().is_ok();
+        if existed {
+            let result = match removed_paths.contains(path) {
+                true => move_path(&source_path, &entry_dirpath.join(path)),
+                false => copy_path(&source_path, &entry_dirpath.join(path)),
+            };
+            result.inspect_err(|_| {
+                let _ = restore_removed_paths(&entry_dirpath, removed_paths);
+                let _ = fs::remove_dir_all(&entry_dirpath);
+            })?;
+        }
+
+        history_paths.push(HistoryPath {
+            path: path.clone(),
+            existed,
+        });
+    }
+
+    Ok(HistoryEntry {
+        id,
+        label: label.to_string(),
+        created_at: Utc::now(),
+        paths: history_paths,
+    })
+}
+
+/// Moves back the paths moved into an entry folder before an edit that failed
+fn restore_removed_paths(entry_dirpath: &Path, removed_paths: &[PathBuf]) -> Result<(), String> {
+    for path in removed_paths {
+        let removed_path = entry_dirpath.join(path);
+        if removed_path.symlink_metadata().is_ok() {
+            move_path(&removed_path, &get_scenes_dirpath().join(path))?;
+        }
+    }
+    Ok(())
+}
+
+/// Records an edit of a scene in its history, so that it can be undone. The
+/// given paths are the files and folders the edit may change, relative to
+/// the scenes folder. Edits that fail are not recorded. Callers must hold the
+/// write lock of the scene.
+pub fn record_scene_edit<T>(
+    scene_name: &str,
+    label: &str,
+    paths: &[PathBuf],
+    edit: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    record_scene_removal(scene_name, label, paths, &[], edit)
+}
+
+/// Records an edit deleting files or folders, which are moved into the
+/// history entry before the edit runs instead of being copied. The edit only
+/// changes the other paths, and the removed ones are moved back if it fails.
+pub fn record_scene_removal<T>(
+    scene_name: &str,
+    label: &str,
+    paths: &[PathBuf],
+    removed_paths: &[PathBuf],
+    edit: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    let entry = take_snapshot(scene_name, label, paths, removed_paths)?;
+    let entry_dirpath = get_history_dirpath(scene_name).join(&entry.id);
+
+    let result = edit();
+    if result.is_err() {
+        let _ = restore_removed_paths(&entry_dirpath, removed_paths);
+        let _ = fs::remove_dir_all(&entry_dirpath);
+        return result;
+    }
+
+    push_history_entry(scene_name, entry)?;
+    result
+}
+
+/// Records an edit changing several scenes with an entry in the history of
+/// each of them, covering only the paths of that scene, so that undoing it in
+/// one scene never overwrites later edits of the others. Callers must hold
+/// the write locks of all the scenes.
+pub fn record_scenes_edit<T>(
+    label: &str,
+    scene_paths: &[(&str, Vec<PathBuf>)],
+    edit: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    let remove_entries = |entries: &[(&str, HistoryEntry)]| {
+        for (scene_name, entry) in entries {
+            let _ = fs::remove_dir_all(get_history_dirpath(scene_name).join(&entry.id));
+        }
+    };
+
+    let mut entries = vec![];
+    for (scene_name, paths) in scene_paths {
+        match take_snapshot(scene_name, label, paths, &[]) {
+            Ok(entry) => entries.push((*scene_name, entry)),
+            Err(err) => {
+                remove_entries(&entries);
+                return Err(err);
+            }
+        }
+    }
+
+    let result = edit();
+    if result.is_err() {
+        remove_entries(&entries);
+        return result;
+    }
+
+    for (scene_name, entry) in entries {
+        push_history_entry(scene_name, entry)?;
+    }
+    result
+}
+
+fn push_history_entry(scene_name: &str, entry: HistoryEntry) -> Result<(), String> {
+    let history_dirpath = get_history_dirpath(scene_name);
+    let mut history = read_scene_history(scene_name)?;
+    history.undo.push(entry);
+
+    // A new edit makes the undone ones unreachable
+    let mut removed_entries = history.redo.drain(..).collect::<Vec<_>>();
+    if history.undo.len() > MAX_HISTORY_LENGTH {
+        let overflow = history.undo.len() - MAX_HISTORY_LENGTH;
+        removed_entries.extend(history.undo.drain(..overflow));
+    }
+    for removed_entry in removed_entries {
+        let _ = fs::remove_dir_all(history_dirpath.join(removed_entry.id));
+    }
+
+    write_scene_history(scene_name, &history)
+}
+
+/// Makes a file or folder match a saved copy in place rather than replacing
+/// it, so that containers bind mounting it keep seeing it. Identical files are
+/// left untouched.
+fn restore_path_in_place(saved_path: &Path, path: &Path) -> Result<(), String> {
+    let Ok(saved_metadata) = saved_path.symlink_metadata() else {
+        return remove_path(path);
+    };
+    let is_dir = path
+        .symlink_metadata()
+        .ok()
+        .map(|metadata| metadata.is_dir());
+
+    if saved_metadata.is_dir() {
+        if is_dir == Some(false) {
+            remove_path(path)?;
+        }
+        fs_extra::dir::create_all(path, false)
+            .map_err(|err| format!("Cannot create folder {}: {err}", path.display()))?;
+
+        let read_dir = |dirpath: &Path| {
+            fs::read_dir(dirpath)
+                .map_err(|err| format!("Cannot read folder {}: {err}", dirpath.display()))
+        };
+        for entry in read_dir(path)?.flatten() {
+            if saved_path
+                .join(entry.file_name())
+                .symlink_metadata()
+                .is_err()
+            {
+                remove_path(&entry.path())?;
+            }
+        }
+        for entry in read_dir(saved_path)?.flatten() {
+            restore_path_in_place(&entry.path(), &path.join(entry.file_name()))?;
+        }
+        return Ok(());
+    }
+
+    if is_dir == Some(true) {
+        remove_path(path)?;
+    }
+    let is_unchanged = fs::metadata(path)
+        .is_ok_and(|metadata| metadata.len() == saved_metadata.len())
+        && fs::read(path).ok() == fs::read(saved_path).ok();
+    if is_unchanged {
+        return Ok(());
+    }
+
+    if let Some(dirpath) = path.parent() {
+        fs_extra::dir::create_all(dirpath, false)
+            .map_err(|err| format!("Cannot create folder {}: {err}", dirpath.display()))?;
+    }
+    // Copying over an existing file truncates it, keeping its inode
+    fs::copy(saved_path, path)
+        .map(|_| ())
+        .map_err(|err| format!("Cannot restore {}: {err}", path.display()))
+}
+
+/// Puts back the paths of an entry as they were when it was recorded,
+/// returning a new entry holding their current state that reverts it. The
+/// current state is put back if any path cannot be restored.
+fn apply_entry(scene_name: &str, entry: HistoryEntry) -> Result<HistoryEntry, String> {
+    let history_dirpath = get_history_dirpath(scene_name);
+    let entry_paths = entry
+        .paths
+        .iter()
+        .map(|history_path| history_path.path.clone())
+        .collect::<Vec<_>>();
+    let reverse_entry = take_snapshot(scene_name, &entry.label, &entry_paths, &[])?;
+
+    let restore = |entry: &HistoryEntry| {
+        let entry_dirpath = history_dirpath.join(&entry.id);
+        for history_path in &entry.paths {
+            let path = get_scenes_dirpath().join(&history_path.path);
+            match history_path.existed {
+                true => restore_path_in_place(&entry_dirpath.join(&history_path.path), &path)?,
+                false => remove_path(&path)?,
+            }
+        }
+        Ok::<_, String>(())
+    };
+
+    if let Err(err) = restore(&entry) {
+        let _ = restore(&reverse_entry);
+        let _ = fs::remove_dir_all(history_dirpath.join(&reverse_entry.id));
+        return Err(err);
+    }
+
+    let _ = fs::remove_dir_all(history_dirpath.join(&entry.id));
+    Ok(reverse_entry)
+}
+
+pub fn get_scene_history(scene_name: &str) -> Result<SceneHistory, String> {
+    read_scene_history(scene_name)
+}
+
+#[derive(Clone, Copy)]
+enum HistoryDirection {
+    Undo,
+    Redo,
+}
+
+fn move_in_history(
+    scene_write_locks: &SceneWriteLocks,
+    scene_name: &str,
+    direction: HistoryDirection,
+) -> Result<String, String> {
+    let pop_entry = |history: &mut SceneHistory| match direction {
+        HistoryDirection::Undo => history.undo.pop(),
+        HistoryDirection::Redo => history.redo.pop(),
+    };
+
+    // All the scenes the entry changes are locked at once to avoid deadlocks
+    let scene_names = pop_entry(&mut read_scene_history(scene_name)?)
+        .map(|entry| entry.get_scene_names())
+        .unwrap_or_default();
+    let _lock = scene_write_locks.lock(
+        &[scene_name]
+            .into_iter()
+            .chain(scene_names.iter().map(String::as_str))
+            .collect::<Vec<_>>(),
+    );
+
+    let mut history = read_scene_history(scene_name)?;
+    let entry = pop_entry(&mut history).ok_or(match direction {
+        HistoryDirection::Undo => format!("Nothing to undo in scene {scene_name}"),
+        HistoryDirection::Redo => format!("Nothing to redo in scene {scene_name}"),
+    })?;
+    if entry.get_scene_names() != scene_names {
+        return Err(format!(
+            "History of scene {scene_name} changed in the meantime, try again"
+        ));
+    }
+
+    // Restored compose files are the app's own writes, not outside changes
+    let previous_hashes = scene_names
+        .iter()
+        .map(|scene_name| docker::get_docker_compose_file_hash(scene_name))
+        .collect::<Vec<_>>();
+
+    let label = entry.label.clone();
+    let entry_paths = entry
+        .paths
+        .iter()
+        .map(|history_path| get_scenes_dirpath().join(&history_path.path))
+        .collect::<Vec<_>>();
+    let reverse_entry =
+        watchers::record_app_writes(&entry_paths, || apply_entry(scene_name, entry))?;
+    for (scene_name, previous_hash) in scene_names.iter().zip(previous_hashes) {
+        if let Some(hash) = docker::get_docker_compose_file_hash(scene_name) {
+            docker::record_docker_compose_file_write(scene_name, previous_hash, hash);
+        }
+    }
+    match direction {
+        HistoryDirection::Undo => history.redo.push(reverse_entry),
+        HistoryDirection::Redo => history.undo.push(reverse_entry),
+    }
+    write_scene_history(scene_name, &history)?;
+
+    Ok(label)
+}
+
+/// Reverts the last edit of the scene, returning its label
+pub fn undo_scene_edit(
+    scene_write_locks: &SceneWriteLocks,
+    scene_name: &str,
+) -> Result<String, String> {
+    move_in_history(scene_write_locks, scene_name, HistoryDirection::Undo)
+}
+
+/// Applies again the last undone edit of the scene, returning its label
+pub fn redo_scene_edit(
+    scene_write_locks: &SceneWriteLocks,
+    scene_name: &str,
+) -> Result<String, String> {
+    move_in_history(scene_write_locks, scene_name, HistoryDirection::Redo)
+}
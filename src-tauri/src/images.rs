@@ -0,0 +1,430 @@
+use std::process::Stdio;
+
+use bollard::{image::CreateImageOptions, Docker};
+use futures::StreamExt;
+use serde::Serialize;
+use serde_yaml::Value;
+use tauri::{AppHandle, Manager};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+    select, spawn,
+    task::JoinHandle,
+};
+
+use crate::{
+    docker::{self, DockerComposeService},
+    scenes,
+    state::{AppState, ServiceKey},
+};
+
+#[derive(Serialize, Clone)]
+pub enum ImageOperation {
+    #[serde(rename = "pull")]
+    Pull,
+    #[serde(rename = "build")]
+    Build,
+}
+
+#[derive(Serialize, Clone)]
+struct ServiceImageEventPayload {
+    /// Missing when the operation has been cancelled
+    operation: Option<ImageOperation>,
+    layer: Option<String>,
+    status: String,
+    current: Option<i64>,
+    total: Option<i64>,
+    done: bool,
+    error: Option<String>,
+}
+
+impl ServiceImageEventPayload {
+    fn message(operation: ImageOperation, status: String, done: bool) -> Self {
+        Self {
+            operation: Some(operation),
+            layer: None,
+            status,
+            current: None,
+            total: None,
+            done,
+            error: None,
+        }
+    }
+}
+
+fn get_service_image_event_name(scene_name: &str, service_id: &str) -> String {
+    format!("{scene_name}-{service_id}-image-event")
+}
+
+/// Finds the definition of a service of the scene, looking into the
+/// included scenes as well
+fn get_scene_service(scene_name: &str, service_id: &str) -> Result<DockerComposeService, String> {
+    let service = scenes::read_scene_services(scene_name)?
+        .into_iter()
+        .find(|service| service.id == service_id)
+        .ok_or(format!(
+            "Cannot find service {service_id} in scene {scene_name}"
+        ))?;
+
+    docker::get_docker_compose_file(&service.scene_name)?
+        .services
+        .remove(service_id)
+        .ok_or(format!(
+            "Cannot find service {service_id} in scene {}",
+            service.scene_name
+        ))
+}
+
+fn get_service_image(service: &DockerComposeService) -> Option<String> {
+    service
+        .extra
+        .get("image")
+        .and_then(Value::as_str)
+        .map(|image| image.to_string())
+}
+
+/// Splits an image reference in the `fromImage` and `tag` parameters of the
+/// docker API, which would otherwise pull every tag of untagged images
+fn split_image_reference(image: &str) -> (String, String) {
+    if image.contains('@') {
+        return (image.to_string(), "".to_string());
+    }
+
+    let name_start = image.rfind('/').map(|index| index + 1).unwrap_or(0);
+    match image[name_start..].rfind(':') {
+        Some(index) => (
+            image[..name_start + index].to_string(),
+            image[name_start + index + 1..].to_string(),
+        ),
+        None => (image.to_string(), "latest".to_string()),
+    }
+}
+
+async fn track_image_operation(
+    app: &AppHandle,
+    scene_name: &str,
+    service_id: &str,
+    handle: JoinHandle<()>,
+) {
+    let state = app.state::<AppState>();
+    let previous_handle = state.service_image_handles.lock().await.insert(
+        ServiceKey {
+            scene_name: scene_name.to_string(),
+            service_id: service_id.to_string(),
+        },
+        handle,
+    );
+
+    if let Some(previous_handle) = previous_handle {
+        previous_handle.abort();
+    }
+}
+
+fn spawn_image_pull(
+    app: &AppHandle,
+    docker: &Docker,
+    scene_name: &str,
+    service_id: &str,
+    image: String,
+) -> JoinHandle<()> {
+    let thread_app = app.to_owned();
+    let thread_docker = docker.clone();
+    let service_image_event_name = get_service_image_event_name(scene_name, service_id);
+
+    spawn(async move {
+        let (from_image, tag) = split_image_reference(&image);
+        let mut pull_stream = thread_docker.create_image(
+            Some(CreateImageOptions {
+                from_image,
+                tag,
+                ..Default::default()
+            }),
+            None,
+            None,
+        );
+
+        while let Some(info) = pull_stream.next().await {
+            let payload = match info {
+                Ok(info) => ServiceImageEventPayload {
+                    operation: Some(ImageOperation::Pull),
+                    layer: info.id,
+                    status: info.status.unwrap_or_default(),
+                    current: info
+                        .progress_detail
+                        .as_ref()
+                        .and_then(|detail| detail.current),
+                    total: info
+                        .progress_detail
+                        .as_ref()
+                        .and_then(|detail| detail.total),
+                    done: false,
+                    error: info.error,
+                },
+                Err(error) => {
+                    thread_app
+                        .emit_all(
+                            service_image_event_name.as_ref(),
+                            ServiceImageEventPayload {
+                                error: Some(format!("Error pulling image {image}: {error}")),
+                                ..ServiceImageEventPayload::message(
+                                    ImageOperation::Pull,
+                                    "Pull failed".to_string(),
+                                    true,
+                                )
+                            },
+                        )
+                        .unwrap();
+                    return;
+                }
+            };
+
+            thread_app
+                .emit_all(service_image_event_name.as_ref(), payload)
+                .unwrap();
+        }
+
+        thread_app
+            .emit_all(
+                service_image_event_name.as_ref(),
+                ServiceImageEventPayload::message(
+                    ImageOperation::Pull,
+                    format!("Pulled image {image}"),
+                    true,
+                ),
+            )
+            .unwrap();
+    })
+}
+
+pub async fn pull_service_image(
+    app: &AppHandle,
+    scene_name: &str,
+    service_id: &str,
+) -> Result<(), String> {
+    let docker = Docker::connect_with_socket_defaults()
+        .map_err(|error| format!("Cannot connect to docker socket: {}", error))?;
+
+    let service = get_scene_service(scene_name, service_id)?;
+    let image = get_service_image(&service).ok_or(format!(
+        "Service {service_id} has no image to pull, it can only be built"
+    ))?;
+
+    let pull_handle = spawn_image_pull(app, &docker, scene_name, service_id, image);
+    track_image_operation(app, scene_name, service_id, pull_handle).await;
+
+    Ok(())
+}
+
+/// Pulls the images of all the services of the scene, returning the ids of
+/// the services being pulled. Services without an `image` key are skipped.
+pub async fn pull_scene_images(app: &AppHandle, scene_name: &str) -> Result<Vec<String>, String> {
+    let docker = Docker::connect_with_socket_defaults()
+        .map_err(|error| format!("Cannot connect to docker socket: {}", error))?;
+
+    let mut service_ids = vec![];
+    for service in scenes::read_scene_services(scene_name)? {
+        let image = docker::get_docker_compose_file(&service.scene_name)?
+            .services
+            .get(&service.id)
+            .and_then(get_service_image);
+        let Some(image) = image else {
+            continue;
+        };
+
+        let pull_handle = spawn_image_pull(app, &docker, scene_name, &service.id, image);
+        track_image_operation(app, scene_name, &service.id, pull_handle).await;
+        service_ids.push(service.id);
+    }
+
+    Ok(service_ids)
+}
+
+pub async fn build_service(
+    app: &AppHandle,
+    scene_name: &str,
+    service_id: &str,
+) -> Result<(), String> {
+    let service = get_scene_service(scene_name, service_id)?;
+    if !service.extra.contains_key("build") {
+        return Err(format!("Service {service_id} has no build configuration"));
+    }
+
+    // Killing the process on drop is what makes the build cancellable
+    let mut child = Command::new("docker-compose")
+        .current_dir(docker::get_docker_compose_dirpath(scene_name))
+        .args([
+            "--project-name",
+            scene_name,
+            "--progress",
+            "plain",
+            "build",
+            service_id,
+        ])
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|error| {
+            format!("Could not start `docker-compose build {service_id}` command: {error}")
+        })?;
+
+    let thread_app = app.to_owned();
+    let thread_service_id = service_id.to_string();
+    let service_image_event_name = get_service_image_event_name(scene_name, service_id);
+    let build_handle = spawn(async move {
+        let emit = |payload: ServiceImageEventPayload| {
+            thread_app
+                .emit_all(service_image_event_name.as_ref(), payload)
+                .unwrap();
+        };
+
+        let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+        let mut stderr = BufReader::new(child.stderr.take().unwrap()).lines();
+        let mut output = vec![];
+        loop {
+            // Build progress is written to stderr, the outcome to stdout
+            let line = select! {
+                Ok(Some(line)) = stdout.next_line() => line,
+                Ok(Some(line)) = stderr.next_line() => line,
+                else => break,
+            };
+
+            emit(ServiceImageEventPayload::message(
+                ImageOperation::Build,
+                line.clone(),
+                false,
+            ));
+            output.push(line);
+        }
+
+        let payload = match child.wait().await {
+            Ok(status) if status.success() => ServiceImageEventPayload::message(
+                ImageOperation::Build,
+                format!("Built service {thread_service_id}"),
+                true,
+            ),
+            Ok(status) => ServiceImageEventPayload {
+                error: Some(format!(
+                    "Error running `docker-compose build {thread_service_id}` command, exited with {status}: {}",
+                    output.last().cloned().unwrap_or_default()
+                )),
+                ..ServiceImageEventPayload::message(
+                    ImageOperation::Build,
+                    "Build failed".to_string(),
+                    true,
+                )
+            },
+            Err(error) => ServiceImageEventPayload {
+                error: Some(format!(
+                    "Error running `docker-compose build {thread_service_id}` command: {error}"
+                )),
+                ..ServiceImageEventPayload::message(
+                    ImageOperation::Build,
+                    "Build failed".to_string(),
+                    true,
+                )
+            },
+        };
+        emit(payload);
+    });
+    track_image_operation(app, scene_name, service_id, build_handle).await;
+
+    Ok(())
+}
+
+pub async fn cancel_service_image_operation(
+    app: &AppHandle,
+    scene_name: &str,
+    service_id: &str,
+) -> Result<(), String> {
+    let state = app.state::<AppState>();
+    let image_handle = state
+        .service_image_handles
+        .lock()
+        .await
+        .remove(&ServiceKey {
+            scene_name: scene_name.to_string(),
+            service_id: service_id.to_string(),
+        });
+
+    // Finished operations keep their handle, there is nothing to cancel then
+    match image_handle.filter(|image_handle| !image_handle.is_finished()) {
+        Some(image_handle) => {
+            image_handle.abort();
+            app.emit_all(
+                get_service_image_event_name(scene_name, service_id).as_ref(),
+                ServiceImageEventPayload {
+                    operation: None,
+                    layer: None,
+                    status: "Cancelled".to_string(),
+                    current: None,
+                    total: None,
+                    done: true,
+                    error: None,
+                },
+            )
+            .unwrap();
+            Ok(())
+        }
+        None => Err(format!(
+            "Could not find a running image operation for scene {} and service {}",
+            scene_name, service_id
+        )),
+    }
+}
+
+#[derive(Serialize)]
+pub struct ServiceImageUpdate {
+    #[serde(rename = "serviceId")]
+    pub service_id: String,
+    pub replica: u32,
+    pub image: String,
+    #[serde(rename = "runningImageId")]
+    pub running_image_id: String,
+    #[serde(rename = "localImageId")]
+    pub local_image_id: Option<String>,
+    #[serde(rename = "hasUpdate")]
+    pub has_update: bool,
+}
+
+/// Compares the image each container of the scene was created from with the
+/// image its reference currently points to locally, after a pull or a build
+/// the two differ until the container is recreated
+pub async fn get_scene_image_updates(scene_name: &str) -> Result<Vec<ServiceImageUpdate>, String> {
+    let docker = Docker::connect_with_socket_defaults()
+        .map_err(|error| format!("Cannot connect to docker socket: {}", error))?;
+
+    let mut image_updates = vec![];
+    for container in docker::get_scene_containers(&docker, scene_name).await? {
+        let inspect = docker
+            .inspect_container(&container.id, None)
+            .await
+            .map_err(|err| format!("Cannot inspect container {}: {err}", container.name))?;
+
+        let (Some(running_image_id), Some(image)) = (
+            inspect.image,
+            inspect.config.and_then(|config| config.image),
+        ) else {
+            continue;
+        };
+
+        let local_image_id = docker
+            .inspect_image(&image)
+            .await
+            .ok()
+            .and_then(|local_image| local_image.id);
+
+        image_updates.push(ServiceImageUpdate {
+            service_id: container.service_id,
+            replica: container.replica,
+            has_update: local_image_id
+                .as_ref()
+                .is_some_and(|local_image_id| *local_image_id != running_image_id),
+            image,
+            running_image_id,
+            local_image_id,
+        });
+    }
+
+    Ok(image_updates)
+}
@@ -4,3 +4,17 @@ pub mod utils;
 pub mod scenes;
 pub mod services;
 pub mod dependencies;
+pub mod logs;
+pub mod log_storage;
+pub mod log_parsing;
+pub mod stats;
+pub mod images;
+pub mod volumes;
+pub mod networks;
+pub mod ports;
+pub mod assets;
+pub mod paths;
+pub mod templates;
+pub mod watchers;
+pub mod history;
+pub mod snapshots;
@@ -0,0 +1,585 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    #[serde(rename = "trace")]
+    Trace,
+    #[serde(rename = "debug")]
+    Debug,
+    #[serde(rename = "info")]
+    Info,
+    #[serde(rename = "warn")]
+    Warn,
+    #[serde(rename = "error")]
+    Error,
+    #[serde(rename = "fatal")]
+    Fatal,
+}
+
+impl LogLevel {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "trace" | "trc" => Some(Self::Trace),
+            "debug" | "dbg" => Some(Self::Debug),
+            "info" | "inf" | "information" | "notice" => Some(Self::Info),
+            "warn" | "wrn" | "warning" => Some(Self::Warn),
+            "error" | "err" | "eror" => Some(Self::Error),
+            "fatal" | "ftl" | "critical" | "crit" | "panic" | "alert" | "emerg" => {
+                Some(Self::Fatal)
+            }
+            _ => None,
+        }
+    }
+
+    /// Numeric levels as used by pino and bunyan
+    fn from_number(number: u64) -> Option<Self> {
+        match number {
+            0..=10 => Some(Self::Trace),
+            11..=20 => Some(Self::Debug),
+            21..=30 => Some(Self::Info),
+            31..=40 => Some(Self::Warn),
+            41..=50 => Some(Self::Error),
+            _ => Some(Self::Fatal),
+        }
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(name) => Self::from_name(name),
+            Value::Number(number) => number.as_u64().and_then(Self::from_number),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub enum LogFormat {
+    #[default]
+    #[serde(rename = "plain")]
+    Plain,
+    #[serde(rename = "json")]
+    Json,
+    #[serde(rename = "logfmt")]
+    Logfmt,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct StyleSpan {
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub foreground: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub bold: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub italic: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub underline: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct LogDetails {
+    pub format: LogFormat,
+    pub level: Option<LogLevel>,
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, Value>,
+    /// Only present when the line contained ANSI styles, concatenating the
+    /// spans texts gives back the stripped text
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub spans: Vec<StyleSpan>,
+}
+
+const LEVEL_KEYS: [&str; 5] = ["level", "lvl", "severity", "levelname", "log.level"];
+const MESSAGE_KEYS: [&str; 4] = ["msg", "message", "@message", "event"];
+
+/// Strips the ANSI escape codes of a log line and detects JSON and logfmt
+/// structured logs, returning the stripped text alongside the details
+pub fn parse_log_text(raw_text: &str) -> (String, LogDetails) {
+    let spans = parse_ansi_spans(raw_text);
+    let text = spans
+        .iter()
+        .map(|span| span.text.as_str())
+        .collect::<String>();
+
+    let is_styled = spans.iter().any(|span| {
+        span.foreground.is_some()
+            || span.background.is_some()
+            || span.bold
+            || span.italic
+            || span.underline
+    });
+    let mut details = parse_json_log(text.trim())
+        .or_else(|| parse_logfmt_log(text.trim()))
+        .unwrap_or_else(|| LogDetails {
+            level: detect_plain_level(&text),
+            ..Default::default()
+        });
+    if is_styled {
+        details.spans = spans;
+    }
+
+    (text, details)
+}
+
+fn parse_json_log(text: &str) -> Option<LogDetails> {
+    if !text.starts_with('{') {
+        return None;
+    }
+
+    let Ok(Value::Object(mut fields)) = serde_json::from_str::<Value>(text) else {
+        return None;
+    };
+
+    let level = LEVEL_KEYS
+        .iter()
+        .find_map(|key| fields.remove(*key))
+        .and_then(|level| LogLevel::from_value(&level));
+    let message = MESSAGE_KEYS
+        .iter()
+        .find_map(|key| fields.remove(*key))
+        .map(|message| match message {
+            Value::String(message) => message,
+            message => message.to_string(),
+        });
+
+    Some(LogDetails {
+        format: LogFormat::Json,
+        level,
+        message,
+        fields: fields.into_iter().collect(),
+        spans: vec![],
+    })
+}
+
+fn parse_logfmt_log(text: &str) -> Option<LogDetails> {
+    let pairs = parse_logfmt_pairs(text)?;
+
+    // A single `key=value` pair is not enough to tell logfmt apart from plain text
+    if pairs.len() < 2 {
+        return None;
+    }
+
+    let mut fields = pairs
+        .into_iter()
+        .map(|(key, value)| (key, Value::String(value)))
+        .collect::<BTreeMap<_, _>>();
+
+    let level = LEVEL_KEYS
+        .iter()
+        .find_map(|key| fields.remove(*key))
+        .and_then(|level| LogLevel::from_value(&level));
+    let message = MESSAGE_KEYS
+        .iter()
+        .find_map(|key| fields.remove(*key))
+        .and_then(|message| message.as_str().map(|message| message.to_string()));
+
+    Some(LogDetails {
+        format: LogFormat::Logfmt,
+        level,
+        message,
+        fields,
+        spans: vec![],
+    })
+}
+
+/// Splits a logfmt line in its pairs, bailing out as soon as a token that
+/// is not a `key=value` pair is found
+fn parse_logfmt_pairs(text: &str) -> Option<Vec<(String, String)>> {
+    let mut pairs = vec![];
+    let mut chars = text.chars().peekable();
+
+    loop {
+        while chars.next_if(|char| char.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut key = String::new();
+        while let Some(char) = chars.next_if(|char| *char != '=' && !char.is_whitespace()) {
+            if char == '"' {
+                return None;
+            }
+            key.push(char);
+        }
+
+        if key.is_empty() || chars.next_if_eq(&'=').is_none() {
+            return None;
+        }
+
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => value.push(chars.next()?),
+                    char => value.push(char),
+                }
+            }
+            if chars.peek().is_some_and(|char| !char.is_whitespace()) {
+                return None;
+            }
+        } else {
+            while let Some(char) = chars.next_if(|char| !char.is_whitespace()) {
+                value.push(char);
+            }
+        }
+
+        pairs.push((key, value));
+    }
+
+    Some(pairs)
+}
+
+/// Looks for a level name leading a plain line, like in `INFO Server started`
+/// or `[error] connection refused`. Timestamps and bracketed words such as
+/// thread names may come first, as in `2024/05/01 12:00:00 [error] 7#7: ...`,
+/// but the first other word ends the search so that level names within the
+/// message, as in `0 errors`, are not mistaken for the level.
+fn detect_plain_level(text: &str) -> Option<LogLevel> {
+    for word in text.split_whitespace().take(4) {
+        let word = word.trim_end_matches([':', '|', ',']);
+        let unbracketed = word
+            .trim_start_matches(['[', '<', '('])
+            .trim_end_matches([']', '>', ')']);
+
+        // Separators such as `|` are left empty
+        let is_timestamp = unbracketed.starts_with(|char: char| char.is_ascii_digit())
+            && unbracketed.contains(['-', ':', '/', '.']);
+        if unbracketed.is_empty() || is_timestamp {
+            continue;
+        }
+
+        let is_bracketed = unbracketed.len() + 2 == word.len();
+        let level = match unbracketed.chars().all(|char| char.is_ascii_alphabetic()) {
+            true => LogLevel::from_name(unbracketed),
+            false => None,
+        };
+        if level.is_some() || !is_bracketed {
+            return level;
+        }
+    }
+
+    None
+}
+
+#[derive(Clone, Default, PartialEq)]
+struct Style {
+    foreground: Option<String>,
+    background: Option<String>,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+}
+
+const ANSI_COLORS: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+fn get_ansi_color(code: u16) -> String {
+    match code {
+        0..=7 => ANSI_COLORS[code as usize].to_string(),
+        8..=15 => format!("bright-{}", ANSI_COLORS[(code - 8) as usize]),
+        code => format!("ansi-{code}"),
+    }
+}
+
+/// Reads the color of a `38`/`48` extended SGR sequence, either in the
+/// `5;n` 256 colors or in the `2;r;g;b` true color form
+fn get_extended_color(params: &mut impl Iterator<Item = u16>) -> Option<String> {
+    match params.next()? {
+        5 => params.next().map(get_ansi_color),
+        2 => {
+            let (red, green, blue) = (params.next()?, params.next()?, params.next()?);
+            Some(format!("#{red:02x}{green:02x}{blue:02x}"))
+        }
+        _ => None,
+    }
+}
+
+fn apply_sgr_params(style: &mut Style, params: &str) {
+    let mut params = params
+        .split(';')
+        .map(|param| param.parse::<u16>().unwrap_or(0));
+
+    while let Some(param) = params.next() {
+        match param {
+            0 => *style = Style::default(),
+            1 => style.bold = true,
+            3 => style.italic = true,
+            4 => style.underline = true,
+            22 => style.bold = false,
+            23 => style.italic = false,
+            24 => style.underline = false,
+            30..=37 => style.foreground = Some(get_ansi_color(param - 30)),
+            38 => style.foreground = get_extended_color(&mut params),
+            39 => style.foreground = None,
+            40..=47 => style.background = Some(get_ansi_color(param - 40)),
+            48 => style.background = get_extended_color(&mut params),
+            49 => style.background = None,
+            90..=97 => style.foreground = Some(get_ansi_color(param - 90 + 8)),
+            100..=107 => style.background = Some(get_ansi_color(param - 100 + 8)),
+            _ => {}
+        }
+    }
+}
+
+/// Splits a text in spans of the same style, dropping every ANSI escape
+/// sequence. Only SGR sequences affect the style, the others are discarded.
+fn parse_ansi_spans(raw_text: &str) -> Vec<StyleSpan> {
+    let mut spans: Vec<StyleSpan> = vec![];
+    let mut style = Style::default();
+    let mut text = String::new();
+
+    let mut push_span = |text: &mut String, style: &Style| {
+        if !text.is_empty() {
+            spans.push(StyleSpan {
+                text: std::mem::take(text),
+                foreground: style.foreground.clone(),
+                background: style.background.clone(),
+                bold: style.bold,
+                italic: style.italic,
+                underline: style.underline,
+            });
+        }
+    };
+
+    let mut chars = raw_text.chars().peekable();
+    while let Some(char) = chars.next() {
+        if char != '\x1b' {
+            text.push(char);
+            continue;
+        }
+
+        match chars.next() {
+            // CSI sequence, parameters are followed by a final byte in the @ to ~ range
+            Some('[') => {
+                let mut params = String::new();
+                let mut final_byte = None;
+                for char in chars.by_ref() {
+                    if ('@'..='~').contains(&char) {
+                        final_byte = Some(char);
+                        break;
+                    }
+                    params.push(char);
+                }
+
+                if final_byte == Some('m') {
+                    let mut next_style = style.clone();
+                    apply_sgr_params(&mut next_style, &params);
+                    if next_style != style {
+                        push_span(&mut text, &style);
+                        style = next_style;
+                    }
+                }
+            }
+            // OSC sequence, terminated by BEL or by ESC \
+            Some(']') => {
+                while let Some(char) = chars.next() {
+                    if char == '\x07' || (char == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            // Other sequences, intermediate bytes in the space to / range are
+            // followed by a final byte, as in ESC ( B
+            Some(' '..='/') => {
+                while chars.next_if(|char| (' '..='/').contains(char)).is_some() {}
+                chars.next();
+            }
+            // Single character sequences such as ESC =
+            _ => {}
+        }
+    }
+    push_span(&mut text, &style);
+
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_fields(fields: &[(&str, Value)]) -> BTreeMap<String, Value> {
+        fields
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_log_text_reads_json_logs() {
+        let cases = [
+            (
+                r#"{"level":"warn","msg":"slow query","took":12}"#,
+                Some(LogLevel::Warn),
+                Some("slow query"),
+                get_fields(&[("took", Value::from(12))]),
+            ),
+            (
+                r#"{"level":50,"time":1,"message":"db down"}"#,
+                Some(LogLevel::Error),
+                Some("db down"),
+                get_fields(&[("time", Value::from(1))]),
+            ),
+            (
+                r#"  {"severity":"CRITICAL","event":{"id":1}}"#,
+                Some(LogLevel::Fatal),
+                Some(r#"{"id":1}"#),
+                get_fields(&[]),
+            ),
+            (
+                r#"{"msg":"no level","level":"verbose"}"#,
+                None,
+                Some("no level"),
+                get_fields(&[]),
+            ),
+        ];
+
+        for (text, level, message, fields) in cases {
+            let (_, details) = parse_log_text(text);
+            assert_eq!(details.format, LogFormat::Json, "{text}");
+            assert_eq!(details.level, level, "{text}");
+            assert_eq!(details.message.as_deref(), message, "{text}");
+            assert_eq!(details.fields, fields, "{text}");
+        }
+    }
+
+    #[test]
+    fn parse_log_text_reads_logfmt_logs() {
+        let cases = [
+            (
+                r#"time=2024-05-01T12:00:00Z level=error msg="db down" retry=3"#,
+                Some(LogLevel::Error),
+                Some("db down"),
+                get_fields(&[
+                    ("time", Value::from("2024-05-01T12:00:00Z")),
+                    ("retry", Value::from("3")),
+                ]),
+            ),
+            (
+                r#"lvl=dbg message="quoted \"value\"" empty="#,
+                Some(LogLevel::Debug),
+                Some(r#"quoted "value""#),
+                get_fields(&[("empty", Value::from(""))]),
+            ),
+        ];
+
+        for (text, level, message, fields) in cases {
+            let (_, details) = parse_log_text(text);
+            assert_eq!(details.format, LogFormat::Logfmt, "{text}");
+            assert_eq!(details.level, level, "{text}");
+            assert_eq!(details.message.as_deref(), message, "{text}");
+            assert_eq!(details.fields, fields, "{text}");
+        }
+    }
+
+    #[test]
+    fn parse_log_text_falls_back_to_plain_text() {
+        let cases = [
+            "key=value",
+            "level=info and then some text",
+            r#"msg="unterminated level=info"#,
+            "{not json",
+            r#"["json", "array"]"#,
+        ];
+
+        for text in cases {
+            let (_, details) = parse_log_text(text);
+            assert_eq!(details.format, LogFormat::Plain, "{text}");
+        }
+    }
+
+    #[test]
+    fn detect_plain_level_only_reads_leading_levels() {
+        let cases = [
+            ("INFO Server started", Some(LogLevel::Info)),
+            ("ERROR: connection refused", Some(LogLevel::Error)),
+            ("[warn] disk almost full", Some(LogLevel::Warn)),
+            ("2024-05-01 12:00:00.123 WRN slow", Some(LogLevel::Warn)),
+            (
+                "2024-05-01T12:00:00Z | debug | cache hit",
+                Some(LogLevel::Debug),
+            ),
+            (
+                "2024/05/01 12:00:00 [error] 7#7: open() failed",
+                Some(LogLevel::Error),
+            ),
+            ("[main] [fatal] out of memory", Some(LogLevel::Fatal)),
+            ("<crit> kernel panic", Some(LogLevel::Fatal)),
+            ("0 errors", None),
+            ("Compiled with 0 error and 2 warnings", None),
+            ("Build finished, error count: 0", None),
+            ("[main] started in 2s, no error", None),
+            ("", None),
+        ];
+
+        for (text, level) in cases {
+            assert_eq!(detect_plain_level(text), level, "{text}");
+        }
+    }
+
+    fn get_span(text: &str, foreground: Option<&str>, bold: bool) -> StyleSpan {
+        StyleSpan {
+            text: text.to_string(),
+            foreground: foreground.map(str::to_string),
+            bold,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse_ansi_spans_applies_sgr_colors() {
+        assert_eq!(
+            parse_ansi_spans("\x1b[1;31merror\x1b[0m: done"),
+            vec![
+                get_span("error", Some("red"), true),
+                get_span(": done", None, false)
+            ]
+        );
+        assert_eq!(
+            parse_ansi_spans("\x1b[38;5;208ma\x1b[38;2;255;0;16mb\x1b[92mc\x1b[39md"),
+            vec![
+                get_span("a", Some("ansi-208"), false),
+                get_span("b", Some("#ff0010"), false),
+                get_span("c", Some("bright-green"), false),
+                get_span("d", None, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ansi_spans_drops_osc_links() {
+        assert_eq!(
+            parse_ansi_spans("see \x1b]8;;https://example.com\x07docs\x1b]8;;\x1b\\ now"),
+            vec![get_span("see docs now", None, false)]
+        );
+    }
+
+    #[test]
+    fn parse_ansi_spans_drops_other_escape_sequences() {
+        assert_eq!(
+            parse_ansi_spans("\x1b(Bplain\x1b=text\x1b[2K\x1b)0!"),
+            vec![get_span("plaintext!", None, false)]
+        );
+    }
+
+    #[test]
+    fn parse_log_text_only_keeps_spans_of_styled_lines() {
+        let (text, details) = parse_log_text("\x1b(Bstarting\x1b[K");
+        assert_eq!(text, "starting");
+        assert!(details.spans.is_empty());
+
+        let (text, details) = parse_log_text("\x1b[32mready\x1b[m");
+        assert_eq!(text, "ready");
+        assert_eq!(details.spans, vec![get_span("ready", Some("green"), false)]);
+    }
+}
@@ -0,0 +1,419 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bollard::{
+    container::{InspectContainerOptions, LogsOptions},
+    Docker,
+};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use tokio::{
+    spawn,
+    time::{sleep, timeout},
+};
+
+use crate::{
+    docker,
+    logs::{parse_log_output, LogType},
+    state::{AppState, ServiceKey},
+    utils::get_config_dirpath,
+};
+
+const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+const MAX_FILES: u64 = 5;
+
+/// Longest wait for a container of the service to start before looking it up
+/// again, when docker events may have been missed
+const CONTAINER_START_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Clone)]
+struct LogCaptureEventPayload {
+    error: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PersistedLogLine {
+    pub session: String,
+    pub run: String,
+    #[serde(rename = "containerName")]
+    pub container_name: String,
+    pub timestamp: Option<DateTime<Utc>>,
+    #[serde(rename = "type")]
+    pub type_name: LogType,
+    pub text: String,
+}
+
+#[derive(Serialize)]
+pub struct PersistedLogRun {
+    pub session: String,
+    pub run: String,
+    #[serde(rename = "containerName")]
+    pub container_name: String,
+    #[serde(rename = "firstTimestamp")]
+    pub first_timestamp: Option<DateTime<Utc>>,
+    #[serde(rename = "lastTimestamp")]
+    pub last_timestamp: Option<DateTime<Utc>>,
+    pub lines: usize,
+}
+
+fn get_log_storage_dirpath(scene_name: &str, service_id: &str) -> PathBuf {
+    get_config_dirpath()
+        .join("logs")
+        .join(scene_name)
+        .join(service_id)
+}
+
+/// Ring buffer of JSON Lines files, the oldest file is deleted once
+/// `MAX_FILES` files of `max_size / MAX_FILES` bytes each have been written
+struct LogStorage {
+    dirpath: PathBuf,
+    max_file_size: u64,
+    current_index: u64,
+    current_size: u64,
+}
+
+impl LogStorage {
+    fn new(scene_name: &str, service_id: &str, max_size: u64) -> Result<Self, String> {
+        let dirpath = get_log_storage_dirpath(scene_name, service_id);
+        fs_extra::dir::create_all(&dirpath, false).map_err(|err| {
+            format!(
+                "Cannot create logs folder for scene {scene_name} and service {service_id}: {err}"
+            )
+        })?;
+
+        let current_index = get_log_file_indexes(&dirpath)?.last().copied().unwrap_or(0);
+        let current_size = fs::metadata(get_log_filepath(&dirpath, current_index))
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+
+        Ok(Self {
+            dirpath,
+            max_file_size: (max_size / MAX_FILES).max(1),
+            current_index,
+            current_size,
+        })
+    }
+
+    fn append(&mut self, line: &PersistedLogLine) -> Result<(), String> {
+        let mut serialized_line = serde_json::to_string(line)
+            .map_err(|err| format!("Cannot serialize log line: {err}"))?;
+        serialized_line.push('\n');
+
+        if self.current_size > 0
+            && self.current_size + serialized_line.len() as u64 > self.max_file_size
+        {
+            self.rotate()?;
+        }
+
+        let filepath = get_log_filepath(&self.dirpath, self.current_index);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&filepath)
+            .map_err(|err| format!("Cannot open log file {:?}: {err}", filepath))?;
+        file.write_all(serialized_line.as_bytes())
+            .map_err(|err| format!("Cannot write log file {:?}: {err}", filepath))?;
+
+        self.current_size += serialized_line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<(), String> {
+        self.current_index += 1;
+        self.current_size = 0;
+
+        for index in get_log_file_indexes(&self.dirpath)? {
+            if index + MAX_FILES <= self.current_index {
+                let filepath = get_log_filepath(&self.dirpath, index);
+                fs::remove_file(&filepath)
+                    .map_err(|err| format!("Cannot delete log file {:?}: {err}", filepath))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn get_log_filepath(dirpath: &Path, index: u64) -> PathBuf {
+    dirpath.join(format!("{index:08}.jsonl"))
+}
+
+fn get_log_file_indexes(dirpath: &Path) -> Result<Vec<u64>, String> {
+    let dir = match fs::read_dir(dirpath) {
+        Ok(dir) => dir,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(format!("Cannot read logs folder {:?}: {err}", dirpath)),
+    };
+
+    let mut indexes = dir
+        .filter_map(|entry| {
+            let filename = entry.ok()?.file_name().to_string_lossy().to_string();
+            filename.strip_suffix(".jsonl")?.parse::<u64>().ok()
+        })
+        .collect::<Vec<_>>();
+    indexes.sort();
+
+    Ok(indexes)
+}
+
+/// Captures the logs of a service on disk while the app is open. The capture
+/// survives container recreation: whenever the followed container goes away
+/// the service's current container is looked up again once one starts. The
+/// capture stops when the logs cannot be stored, emitting the error on
+/// `{scene_name}-{service_id}-log-capture-event`.
+pub async fn start_persisting_service_logs(
+    app: &AppHandle,
+    scene_name: &str,
+    service_id: &str,
+    max_size: Option<u64>,
+) -> Result<(), String> {
+    let docker = Docker::connect_with_socket_defaults()
+        .map_err(|error| format!("Cannot connect to docker socket: {}", error))?;
+
+    let storage = LogStorage::new(scene_name, service_id, max_size.unwrap_or(DEFAULT_MAX_SIZE))?;
+
+    // Lines already on disk from previous captures must not be written again
+    let last_timestamps = get_persisted_service_log_runs(scene_name, service_id)?
+        .into_iter()
+        .filter_map(|run| Some((run.run, run.last_timestamp?)))
+        .collect::<HashMap<_, _>>();
+
+    let state = app.state::<AppState>();
+    let session = state.session_id.clone();
+    let service_key = ServiceKey {
+        scene_name: scene_name.to_string(),
+        service_id: service_id.to_string(),
+    };
+
+    // Locked until the handle is stored, so that a capture failing right away
+    // cannot remove its handle before it is there
+    let mut capture_handles = state.service_log_capture_handles.lock().await;
+
+    let thread_app = app.to_owned();
+    let thread_scene_name = scene_name.to_string();
+    let thread_service_id = service_id.to_string();
+    let capture_handle = spawn(async move {
+        let error = capture_service_logs(
+            &docker,
+            &thread_scene_name,
+            &thread_service_id,
+            &session,
+            storage,
+            last_timestamps,
+        )
+        .await;
+
+        // A stopped capture must not look active
+        thread_app
+            .state::<AppState>()
+            .service_log_capture_handles
+            .lock()
+            .await
+            .remove(&ServiceKey {
+                scene_name: thread_scene_name.clone(),
+                service_id: thread_service_id.clone(),
+            });
+        thread_app
+            .emit_all(
+                &format!("{thread_scene_name}-{thread_service_id}-log-capture-event"),
+                LogCaptureEventPayload { error },
+            )
+            .unwrap();
+    });
+
+    if let Some(previous_handle) = capture_handles.insert(service_key, capture_handle) {
+        previous_handle.abort();
+    }
+
+    Ok(())
+}
+
+/// Appends the logs of the first container of a service to the storage, only
+/// returning the error that stopped the capture. Stopped containers are read
+/// once, then the capture waits for a container of the service to start.
+async fn capture_service_logs(
+    docker: &Docker,
+    scene_name: &str,
+    service_id: &str,
+    session: &str,
+    mut storage: LogStorage,
+    mut last_timestamps: HashMap<String, DateTime<Utc>>,
+) -> String {
+    let mut started_containers =
+        docker::get_started_containers(docker, scene_name, Some(service_id));
+
+    loop {
+        // With multiple replicas only the first one is captured
+        let container = docker::get_scene_containers(docker, scene_name)
+            .await
+            .ok()
+            .and_then(|containers| {
+                containers
+                    .into_iter()
+                    .find(|container| container.service_id == service_id)
+            });
+
+        if let Some(container) = &container {
+            let run = container.id.chars().take(12).collect::<String>();
+            let last_timestamp = last_timestamps.get(&run).copied();
+            let mut logs_stream = docker.logs::<String>(
+                &container.id,
+                Some(LogsOptions::<String> {
+                    follow: true,
+                    tail: "all".to_string(),
+                    stderr: true,
+                    stdout: true,
+                    timestamps: true,
+                    since: last_timestamp
+                        .map(|timestamp| timestamp.timestamp())
+                        .unwrap_or_default(),
+                    ..Default::default()
+                }),
+            );
+
+            while let Some(Ok(log)) = logs_stream.next().await {
+                let Some(log_line) = parse_log_output(log) else {
+                    continue;
+                };
+
+                if let Some(timestamp) = log_line.timestamp {
+                    if last_timestamp.is_some_and(|last_timestamp| timestamp <= last_timestamp) {
+                        continue;
+                    }
+                    last_timestamps.insert(run.clone(), timestamp);
+                }
+
+                let result = storage.append(&PersistedLogLine {
+                    session: session.to_string(),
+                    run: run.clone(),
+                    container_name: container.name.clone(),
+                    timestamp: log_line.timestamp,
+                    type_name: log_line.type_name,
+                    text: log_line.text,
+                });
+                if let Err(err) = result {
+                    return format!("Stopped persisting logs of service {service_id}: {err}");
+                }
+            }
+        }
+
+        let is_running = match &container {
+            Some(container) => docker
+                .inspect_container(&container.id, None::<InspectContainerOptions>)
+                .await
+                .ok()
+                .and_then(|container| container.state?.running)
+                .unwrap_or(false),
+            None => false,
+        };
+        // Only the stream was interrupted
+        if is_running {
+            sleep(Duration::from_secs(1)).await;
+            continue;
+        }
+
+        // Looking the container up again in case a start event was missed
+        let _ = timeout(CONTAINER_START_TIMEOUT, started_containers.next()).await;
+    }
+}
+
+pub async fn stop_persisting_service_logs(
+    state: State<'_, AppState>,
+    scene_name: &str,
+    service_id: &str,
+) -> Result<(), String> {
+    let capture_handle = state
+        .service_log_capture_handles
+        .lock()
+        .await
+        .remove(&ServiceKey {
+            scene_name: scene_name.to_string(),
+            service_id: service_id.to_string(),
+        });
+
+    match capture_handle {
+        Some(capture_handle) => {
+            capture_handle.abort();
+            Ok(())
+        }
+        None => Err(format!(
+            "Could not find the log persisting process for scene {} and service {}",
+            scene_name, service_id
+        )),
+    }
+}
+
+fn read_persisted_log_lines(
+    scene_name: &str,
+    service_id: &str,
+) -> Result<Vec<PersistedLogLine>, String> {
+    let dirpath = get_log_storage_dirpath(scene_name, service_id);
+
+    let mut log_lines = vec![];
+    for index in get_log_file_indexes(&dirpath)? {
+        let filepath = get_log_filepath(&dirpath, index);
+        let content = fs::read_to_string(&filepath)
+            .map_err(|err| format!("Cannot read log file {:?}: {err}", filepath))?;
+
+        // A line could have been cut short by a crash, it's safe to skip it
+        log_lines.extend(
+            content
+                .lines()
+                .filter_map(|line| serde_json::from_str::<PersistedLogLine>(line).ok()),
+        );
+    }
+
+    Ok(log_lines)
+}
+
+pub fn get_persisted_service_log_runs(
+    scene_name: &str,
+    service_id: &str,
+) -> Result<Vec<PersistedLogRun>, String> {
+    let mut runs: Vec<PersistedLogRun> = vec![];
+    for log_line in read_persisted_log_lines(scene_name, service_id)? {
+        let run = runs
+            .iter_mut()
+            .find(|run| run.session == log_line.session && run.run == log_line.run);
+
+        match run {
+            Some(run) => {
+                run.lines += 1;
+                if log_line.timestamp.is_some() {
+                    run.first_timestamp = run.first_timestamp.or(log_line.timestamp);
+                    run.last_timestamp = log_line.timestamp;
+                }
+            }
+            None => runs.push(PersistedLogRun {
+                session: log_line.session,
+                run: log_line.run,
+                container_name: log_line.container_name,
+                first_timestamp: log_line.timestamp,
+                last_timestamp: log_line.timestamp,
+                lines: 1,
+            }),
+        }
+    }
+
+    Ok(runs)
+}
+
+pub fn get_persisted_service_logs(
+    scene_name: &str,
+    service_id: &str,
+    session: Option<&str>,
+    run: Option<&str>,
+) -> Result<Vec<PersistedLogLine>, String> {
+    Ok(read_persisted_log_lines(scene_name, service_id)?
+        .into_iter()
+        .filter(|log_line| session.is_none_or(|session| log_line.session == session))
+        .filter(|log_line| run.is_none_or(|run| log_line.run == run))
+        .collect())
+}
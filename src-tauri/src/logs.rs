@@ -0,0 +1,764 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufWriter, Write},
+    time::Duration,
+};
+
+use bollard::{
+    container::{InspectContainerOptions, LogOutput, LogsOptions},
+    Docker,
+};
+use chrono::{DateTime, Utc};
+use futures::{
+    stream::{self, select_all},
+    Stream, StreamExt,
+};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use tokio::{select, spawn, time::interval, time::sleep};
+
+use crate::{
+    docker::{self, DockerComposeContainer},
+    log_parsing::{parse_log_text, LogDetails, LogLevel},
+    state::{AppState, ServiceKey},
+    utils::get_formatted_date,
+};
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub enum LogType {
+    #[serde(rename = "stderr")]
+    StdErr,
+    #[serde(rename = "stdout")]
+    StdOut,
+}
+
+pub struct LogLine {
+    pub timestamp: Option<DateTime<Utc>>,
+    pub text: String,
+    pub type_name: LogType,
+}
+
+/// Splits a docker log frame requested with `timestamps: true` into its
+/// timestamp and text, ignoring frames that are neither stdout nor stderr
+pub fn parse_log_output(log: LogOutput) -> Option<LogLine> {
+    let type_name = match log {
+        LogOutput::StdOut { message: _ } => LogType::StdOut,
+        LogOutput::StdErr { message: _ } => LogType::StdErr,
+        _ => return None,
+    };
+
+    let log_string = log.to_string();
+    let (timestamp, text) = match log_string.split_once(' ') {
+        None => (None, log_string),
+        Some((timestamp, text)) => (
+            DateTime::parse_from_rfc3339(timestamp)
+                .map(|date| date.into())
+                .ok(),
+            text.to_string(),
+        ),
+    };
+
+    Some(LogLine {
+        timestamp,
+        text,
+        type_name,
+    })
+}
+
+#[derive(Serialize, Clone)]
+struct ServiceLogEventPayload {
+    text: String,
+    timestamp: String,
+    #[serde(rename = "type")]
+    type_name: LogType,
+    clear: bool,
+    /// Marks the restart or the recreation of the followed container
+    separator: bool,
+    #[serde(flatten)]
+    details: LogDetails,
+}
+
+/// Lines with a detected level lower than `min_level` are not emitted, lines
+/// without a detectable level are always emitted
+fn is_below_min_level(details: &LogDetails, min_level: Option<LogLevel>) -> bool {
+    match (details.level, min_level) {
+        (Some(level), Some(min_level)) => level < min_level,
+        _ => false,
+    }
+}
+
+/// Follows the logs of a service, reattaching to its container whenever it's
+/// restarted or recreated. A separator line marks each reattachment, and only
+/// the lines newer than the last emitted one are followed on a restarted
+/// container so that nothing is emitted twice.
+pub async fn start_emitting_service_logs(
+    app: &AppHandle,
+    scene_name: &str,
+    service_id: &str,
+    min_level: Option<LogLevel>,
+) -> Result<(), String> {
+    let docker = Docker::connect_with_socket_defaults()
+        .map_err(|error| format!("Cannot connect to docker socket: {}", error))?;
+
+    let thread_app = app.to_owned();
+    let thread_scene_name = scene_name.to_string();
+    let thread_service_id = service_id.to_string();
+    let logs_handle = spawn(async move {
+        let service_log_event_name = format!("{thread_scene_name}-{thread_service_id}-log-event");
+        let emit_message = |text: String, clear: bool, separator: bool| {
+            thread_app
+                .emit_all(
+                    service_log_event_name.as_ref(),
+                    ServiceLogEventPayload {
+                        text,
+                        timestamp: get_formatted_date(None),
+                        clear,
+                        separator,
+                        type_name: LogType::StdErr,
+                        details: LogDetails::default(),
+                    },
+                )
+                .unwrap();
+        };
+
+        // Container id and start date of the last followed container
+        let mut last_run: Option<(String, Option<String>)> = None;
+        let mut last_timestamp: Option<DateTime<Utc>> = None;
+
+        loop {
+            // Messages clear the logs only until the first container is found
+            let is_attached = last_run.is_some();
+
+            let container = match docker::get_scene_containers(&docker, &thread_scene_name).await {
+                Err(err) => {
+                    emit_message(
+                        format!("Error getting containers: {}", err),
+                        !is_attached,
+                        false,
+                    );
+                    sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+                Ok(containers) => containers
+                    .into_iter()
+                    .find(|container| container.service_id == thread_service_id),
+            };
+
+            let Some(container) = container else {
+                if !is_attached {
+                    emit_message(
+                        "Container does not exist for this service...".to_string(),
+                        true,
+                        false,
+                    );
+                }
+                sleep(Duration::from_secs(1)).await;
+                continue;
+            };
+
+            let container_state = docker
+                .inspect_container(&container.id, None::<InspectContainerOptions>)
+                .await
+                .ok()
+                .and_then(|container| container.state);
+            let is_running = container_state
+                .as_ref()
+                .and_then(|state| state.running)
+                .unwrap_or(false);
+            let run = (
+                container.id.clone(),
+                container_state.and_then(|state| state.started_at),
+            );
+
+            match &last_run {
+                // The followed container has stopped and has not been started again yet
+                Some(last_run) if *last_run == run && !is_running => {
+                    sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+                // Only the stream was interrupted, the container is the same
+                Some(last_run) if *last_run == run => {}
+                Some((last_container_id, _)) => {
+                    let text = match *last_container_id == container.id {
+                        true => format!("Container {} has been restarted", container.name),
+                        false => {
+                            last_timestamp = None;
+                            format!("Container {} has been recreated", container.name)
+                        }
+                    };
+                    emit_message(text, false, true);
+                }
+                None => {}
+            }
+            last_run = Some(run);
+
+            let mut logs_stream = docker.logs::<String>(
+                &container.id,
+                Some(LogsOptions::<String> {
+                    follow: true,
+                    tail: "all".to_string(),
+                    stderr: true,
+                    stdout: true,
+                    timestamps: true,
+                    since: last_timestamp
+                        .map(|timestamp| timestamp.timestamp())
+                        .unwrap_or_default(),
+                    ..Default::default()
+                }),
+            );
+
+            while let Some(log) = logs_stream.next().await {
+                match log {
+                    Ok(log) => {
+                        if let Some(log_line) = parse_log_output(log) {
+                            if let Some(timestamp) = log_line.timestamp {
+                                if last_timestamp.is_some_and(|last| timestamp <= last) {
+                                    continue;
+                                }
+                                last_timestamp = Some(timestamp);
+                            }
+
+                            let (text, details) = parse_log_text(&log_line.text);
+                            if is_below_min_level(&details, min_level) {
+                                continue;
+                            }
+
+                            thread_app
+                                .emit_all(
+                                    service_log_event_name.as_ref(),
+                                    ServiceLogEventPayload {
+                                        text,
+                                        timestamp: log_line
+                                            .timestamp
+                                            .map(|timestamp| get_formatted_date(Some(timestamp)))
+                                            .unwrap_or_default(),
+                                        clear: false,
+                                        separator: false,
+                                        type_name: log_line.type_name,
+                                        details,
+                                    },
+                                )
+                                .unwrap();
+                        }
+                    }
+                    Err(error) => {
+                        emit_message(format!("Logs stream interrupted: {}", error), false, false);
+                        break;
+                    }
+                }
+            }
+
+            sleep(Duration::from_secs(1)).await;
+        }
+    });
+
+    let state = app.state::<AppState>();
+    if let Some(previous_handle) = state.service_log_handles.lock().await.insert(
+        ServiceKey {
+            scene_name: scene_name.to_string(),
+            service_id: service_id.to_string(),
+        },
+        logs_handle,
+    ) {
+        previous_handle.abort();
+    }
+
+    Ok(())
+}
+
+pub async fn stop_emitting_service_logs(
+    state: State<'_, AppState>,
+    scene_name: &str,
+    service_id: &str,
+) -> Result<(), String> {
+    let service_log_handles = state.service_log_handles.lock().await;
+    let log_handle = service_log_handles.get(&ServiceKey {
+        scene_name: scene_name.to_string(),
+        service_id: service_id.to_string(),
+    });
+
+    match log_handle {
+        Some(log_handle) => {
+            log_handle.abort();
+            Ok(())
+        }
+        None => Err(format!(
+            "Could not find the log emitting process for scene {} and service {}",
+            scene_name, service_id
+        )),
+    }
+}
+
+#[derive(Serialize, Clone)]
+struct SceneLogEventPayload {
+    #[serde(rename = "serviceId")]
+    service_id: String,
+    replica: u32,
+    text: String,
+    timestamp: String,
+    #[serde(rename = "type")]
+    type_name: LogType,
+    clear: bool,
+    #[serde(flatten)]
+    details: LogDetails,
+}
+
+struct SceneLogLine {
+    service_id: String,
+    replica: u32,
+    log_line: LogLine,
+}
+
+/// Logs coming from different containers are buffered for this long and
+/// sorted by their docker timestamp before being emitted
+const SCENE_LOGS_FLUSH_INTERVAL: Duration = Duration::from_millis(250);
+
+pub async fn start_emitting_scene_logs(
+    app: &AppHandle,
+    scene_name: &str,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    min_level: Option<LogLevel>,
+) -> Result<(), String> {
+    let docker = Docker::connect_with_socket_defaults()
+        .map_err(|error| format!("Cannot connect to docker socket: {}", error))?;
+
+    let service_ids = docker::get_scene_service_ids(scene_name)?
+        .into_iter()
+        .filter(|service_id| match &include {
+            Some(include) => include.contains(service_id),
+            None => true,
+        })
+        .filter(|service_id| match &exclude {
+            Some(exclude) => !exclude.contains(service_id),
+            None => true,
+        })
+        .collect::<HashSet<_>>();
+
+    if service_ids.is_empty() {
+        return Err(format!(
+            "No services of scene {scene_name} are left to follow after applying the filters"
+        ));
+    }
+
+    let thread_app = app.to_owned();
+    let thread_scene_name = scene_name.to_string();
+    let logs_handle = spawn(async move {
+        let scene_log_event_name = format!("{thread_scene_name}-log-event");
+        let emit_error = |text: String| {
+            thread_app
+                .emit_all(
+                    scene_log_event_name.as_ref(),
+                    SceneLogEventPayload {
+                        service_id: "".to_string(),
+                        replica: 0,
+                        text,
+                        timestamp: get_formatted_date(None),
+                        clear: true,
+                        type_name: LogType::StdErr,
+                        details: LogDetails::default(),
+                    },
+                )
+                .unwrap();
+        };
+
+        let containers = loop {
+            match docker::get_scene_containers(&docker, &thread_scene_name).await {
+                Err(err) => emit_error(format!("Error getting containers: {}", err)),
+                Ok(containers) => {
+                    let containers = containers
+                        .into_iter()
+                        .filter(|container| service_ids.contains(&container.service_id))
+                        .collect::<Vec<_>>();
+
+                    if !containers.is_empty() {
+                        break containers;
+                    }
+
+                    emit_error("Containers do not exist for this scene...".to_string());
+                }
+            }
+
+            sleep(Duration::from_secs(1)).await;
+        };
+
+        // Subscribed before reading the logs so that no start is missed
+        let mut started_containers =
+            docker::get_started_containers(&docker, &thread_scene_name, None);
+
+        // Streams end with a `None` log, telling that the container is no
+        // longer followed
+        let get_logs_stream = |container: &DockerComposeContainer, follow: bool, since: i64| {
+            let container_id = container.id.clone();
+            let service_id = container.service_id.clone();
+            let replica = container.replica;
+            docker
+                .logs::<String>(
+                    &container.id,
+                    Some(LogsOptions::<String> {
+                        follow,
+                        since,
+                        tail: "all".to_string(),
+                        stderr: true,
+                        stdout: true,
+                        timestamps: true,
+                        ..Default::default()
+                    }),
+                )
+                .map(Some)
+                .chain(stream::once(async { None }))
+                .map(move |log| (container_id.clone(), service_id.clone(), replica, log))
+                .boxed()
+        };
+
+        let emit_lines = |buffer: &mut Vec<SceneLogLine>| {
+            buffer.sort_by_key(|scene_log_line| scene_log_line.log_line.timestamp);
+            for scene_log_line in buffer.drain(..) {
+                let (text, details) = parse_log_text(&scene_log_line.log_line.text);
+                if is_below_min_level(&details, min_level) {
+                    continue;
+                }
+
+                thread_app
+                    .emit_all(
+                        scene_log_event_name.as_ref(),
+                        SceneLogEventPayload {
+                            service_id: scene_log_line.service_id,
+                            replica: scene_log_line.replica,
+                            text,
+                            timestamp: scene_log_line
+                                .log_line
+                                .timestamp
+                                .map(|timestamp| get_formatted_date(Some(timestamp)))
+                                .unwrap_or_default(),
+                            clear: false,
+                            type_name: scene_log_line.log_line.type_name,
+                            details,
+                        },
+                    )
+                    .unwrap();
+            }
+        };
+
+        // Lines are only sorted within each flush, so the existing lines are read
+        // and sorted as a whole before following the new ones
+        let backlog_started_at = Utc::now();
+        let mut last_timestamps = HashMap::new();
+        let mut buffer: Vec<SceneLogLine> = vec![];
+        let mut backlog_stream = select_all(
+            containers
+                .iter()
+                .map(|container| get_logs_stream(container, false, 0)),
+        );
+        while let Some((container_id, service_id, replica, log)) = backlog_stream.next().await {
+            match log {
+                Some(Ok(log)) => {
+                    if let Some(log_line) = parse_log_output(log) {
+                        if let Some(timestamp) = log_line.timestamp {
+                            last_timestamps.insert(container_id, timestamp);
+                        }
+                        buffer.push(SceneLogLine {
+                            service_id,
+                            replica,
+                            log_line,
+                        });
+                    }
+                }
+                Some(Err(error)) => emit_error(format!(
+                    "Cannot read logs of {service_id} (replica {replica}): {error}"
+                )),
+                None => {}
+            }
+        }
+        emit_lines(&mut buffer);
+
+        // Following starts from the second the backlog was requested in, lines
+        // already read are skipped. Containers started later on are followed
+        // from their last read line, or from their first one when recreated.
+        let mut followed_container_ids = containers
+            .iter()
+            .map(|container| container.id.clone())
+            .collect::<HashSet<_>>();
+        let mut logs_stream = select_all(
+            containers
+                .iter()
+                .map(|container| get_logs_stream(container, true, backlog_started_at.timestamp())),
+        );
+        let mut flush_interval = interval(SCENE_LOGS_FLUSH_INTERVAL);
+        loop {
+            select! {
+                log = logs_stream.next(), if !logs_stream.is_empty() => match log {
+                    None => {}
+                    Some((container_id, service_id, replica, Some(Ok(log)))) => {
+                        if let Some(log_line) = parse_log_output(log) {
+                            if let Some(timestamp) = log_line.timestamp {
+                                if last_timestamps.get(&container_id).is_some_and(|last| timestamp <= *last) {
+                                    continue;
+                                }
+                                last_timestamps.insert(container_id, timestamp);
+                            }
+                            buffer.push(SceneLogLine {
+                                service_id,
+                                replica,
+                                log_line,
+                            });
+                        }
+                    }
+                    Some((_, service_id, replica, Some(Err(error)))) => {
+                        emit_lines(&mut buffer);
+                        emit_error(format!(
+                            "Logs stream interrupted for {service_id} (replica {replica}): {error}"
+                        ));
+                    }
+                    Some((container_id, _, _, None)) => {
+                        followed_container_ids.remove(&container_id);
+                    }
+                },
+                Some(container) = started_containers.next() => {
+                    if service_ids.contains(&container.service_id)
+                        && followed_container_ids.insert(container.id.clone())
+                    {
+                        let since = last_timestamps
+                            .get(&container.id)
+                            .map(|timestamp| timestamp.timestamp())
+                            .unwrap_or_default();
+                        logs_stream.push(get_logs_stream(&container, true, since));
+                    }
+                },
+                _ = flush_interval.tick() => emit_lines(&mut buffer),
+            }
+        }
+    });
+
+    let state = app.state::<AppState>();
+    if let Some(previous_handle) = state
+        .scene_log_handles
+        .lock()
+        .await
+        .insert(scene_name.to_string(), logs_handle)
+    {
+        previous_handle.abort();
+    }
+
+    Ok(())
+}
+
+pub async fn stop_emitting_scene_logs(
+    state: State<'_, AppState>,
+    scene_name: &str,
+) -> Result<(), String> {
+    let scene_log_handles = state.scene_log_handles.lock().await;
+    let log_handle = scene_log_handles.get(scene_name);
+
+    match log_handle {
+        Some(log_handle) => {
+            log_handle.abort();
+            Ok(())
+        }
+        None => Err(format!(
+            "Could not find the log emitting process for scene {}",
+            scene_name
+        )),
+    }
+}
+
+#[derive(Deserialize)]
+pub enum LogExportFormat {
+    #[serde(rename = "text")]
+    Text,
+    #[serde(rename = "jsonl")]
+    JsonLines,
+}
+
+/// Every filter is optional, logs of both streams are exported by default
+#[derive(Deserialize, Default)]
+pub struct LogExportFilters {
+    /// RFC 3339 dates
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub streams: Option<Vec<LogType>>,
+}
+
+#[derive(Serialize)]
+struct LogExportEntry<'a> {
+    timestamp: Option<String>,
+    stream: &'a LogType,
+    service: &'a str,
+    replica: u32,
+    text: &'a str,
+}
+
+fn parse_log_export_date(date: Option<&str>) -> Result<Option<DateTime<Utc>>, String> {
+    date.map(|date| {
+        DateTime::parse_from_rfc3339(date)
+            .map(|date| date.into())
+            .map_err(|err| format!("Invalid date {date}, expected RFC 3339 format: {err}"))
+    })
+    .transpose()
+}
+
+/// Writes the logs of the containers of a service to `filepath`, replicas one
+/// after the other, returning the number of exported lines. Logs are streamed
+/// straight from docker into the file so they never have to go through the
+/// webview.
+pub async fn export_service_logs(
+    scene_name: &str,
+    service_id: &str,
+    filepath: &str,
+    format: LogExportFormat,
+    filters: LogExportFilters,
+) -> Result<usize, String> {
+    let since = parse_log_export_date(filters.since.as_deref())?;
+    let until = parse_log_export_date(filters.until.as_deref())?;
+    let streams = filters
+        .streams
+        .unwrap_or(vec![LogType::StdOut, LogType::StdErr]);
+    if streams.is_empty() {
+        return Err("No log streams selected, select stdout, stderr or both".to_string());
+    }
+
+    let docker = Docker::connect_with_socket_defaults()
+        .map_err(|error| format!("Cannot connect to docker socket: {}", error))?;
+
+    // Stopped containers are included, exporting the logs of a crashed
+    // service is the main reason to export them
+    let containers = docker::get_scene_containers(&docker, scene_name)
+        .await?
+        .into_iter()
+        .filter(|container| container.service_id == service_id)
+        .collect::<Vec<_>>();
+    if containers.is_empty() {
+        return Err(format!(
+            "Container does not exist for service {service_id} in scene {scene_name}"
+        ));
+    }
+
+    let file = File::create(filepath)
+        .map_err(|err| format!("Cannot create logs export file {filepath}: {err}"))?;
+    let mut writer = BufWriter::new(file);
+
+    let mut exported_lines = 0;
+    for container in &containers {
+        let logs_stream = docker.logs::<String>(
+            &container.name,
+            Some(LogsOptions::<String> {
+                follow: false,
+                tail: "all".to_string(),
+                stdout: streams.contains(&LogType::StdOut),
+                stderr: streams.contains(&LogType::StdErr),
+                timestamps: true,
+                // Docker only filters by whole seconds, the exact bounds are checked below
+                since: since.map(|since| since.timestamp()).unwrap_or_default(),
+                until: until.map(|until| until.timestamp() + 1).unwrap_or_default(),
+            }),
+        );
+        let source = match containers.len() {
+            1 => service_id.to_string(),
+            _ => format!("{service_id} (replica {})", container.replica),
+        };
+        exported_lines += export_container_logs(
+            logs_stream,
+            &mut writer,
+            &format,
+            since,
+            until,
+            container,
+            &source,
+        )
+        .await
+        .map_err(|err| format!("Cannot export logs of {source}: {err}"))?;
+    }
+
+    writer
+        .flush()
+        .map_err(|err| format!("Cannot write logs export file {filepath}: {err}"))?;
+
+    Ok(exported_lines)
+}
+
+/// Writes the lines of a container within the time range, text lines being
+/// prefixed by `source`
+async fn export_container_logs(
+    mut logs_stream: impl Stream<Item = Result<LogOutput, bollard::errors::Error>> + Unpin,
+    writer: &mut impl Write,
+    format: &LogExportFormat,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    container: &DockerComposeContainer,
+    source: &str,
+) -> Result<usize, String> {
+    let mut exported_lines = 0;
+    while let Some(log) = logs_stream.next().await {
+        let log = log.map_err(|err| format!("Logs stream interrupted: {err}"))?;
+        let Some(log_line) = parse_log_output(log) else {
+            continue;
+        };
+
+        let is_in_range = match log_line.timestamp {
+            Some(timestamp) => {
+                since.map(|since| timestamp >= since).unwrap_or(true)
+                    && until.map(|until| timestamp <= until).unwrap_or(true)
+            }
+            None => true,
+        };
+        if !is_in_range {
+            continue;
+        }
+
+        let timestamp = log_line.timestamp.map(|timestamp| timestamp.to_rfc3339());
+        let text = log_line.text.trim_end_matches(['\n', '\r']);
+        let line = match format {
+            LogExportFormat::Text => {
+                let stream = match log_line.type_name {
+                    LogType::StdOut => "stdout",
+                    LogType::StdErr => "stderr",
+                };
+                format!(
+                    "{} [{stream}] {source}: {text}",
+                    timestamp.unwrap_or_default()
+                )
+            }
+            LogExportFormat::JsonLines => serde_json::to_string(&LogExportEntry {
+                timestamp,
+                stream: &log_line.type_name,
+                service: &container.service_id,
+                replica: container.replica,
+                text,
+            })
+            .map_err(|err| format!("Cannot serialize log line: {err}"))?,
+        };
+
+        writeln!(writer, "{line}").map_err(|err| format!("Cannot write file: {err}"))?;
+        exported_lines += 1;
+    }
+
+    Ok(exported_lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn min_level_only_filters_lines_with_a_level() {
+        let cases = [
+            ("DEBUG cache hit", Some(LogLevel::Info), true),
+            ("INFO started", Some(LogLevel::Info), false),
+            ("ERROR failed", Some(LogLevel::Warn), false),
+            (
+                r#"{"level":"trace","msg":"tick"}"#,
+                Some(LogLevel::Debug),
+                true,
+            ),
+            ("level=warn msg=slow", Some(LogLevel::Error), true),
+            ("no level at all", Some(LogLevel::Fatal), false),
+            ("DEBUG cache hit", None, false),
+        ];
+
+        for (text, min_level, is_below) in cases {
+            let (_, details) = parse_log_text(text);
+            assert_eq!(is_below_min_level(&details, min_level), is_below, "{text}");
+        }
+    }
+}
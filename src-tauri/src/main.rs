@@ -19,17 +19,60 @@ async fn main() -> Result<(), String> {
             scenes::get_scene_services,
             scenes::run_scene,
             scenes::stop_scene,
+            scenes::start_emitting_scene_logs,
+            scenes::stop_emitting_scene_logs,
+            scenes::start_emitting_scene_stats,
+            scenes::stop_emitting_scene_stats,
+            scenes::pull_scene_images,
+            scenes::get_scene_image_updates,
+            scenes::get_scene_volumes,
+            scenes::delete_scene_volumes,
+            scenes::get_scene_networks,
+            scenes::get_scene_port_conflicts,
+            scenes::get_scene_variables,
+            scenes::set_scene_variables,
+            scenes::start_watching_scene,
+            scenes::stop_watching_scene,
+            scenes::get_scene_history,
+            scenes::undo_scene_edit,
+            scenes::redo_scene_edit,
+            scenes::take_scene_snapshot,
+            scenes::list_snapshots,
+            scenes::diff_snapshot,
+            scenes::restore_snapshot,
+            scenes::delete_snapshot,
             services::get_service,
             services::create_service,
             services::delete_service,
+            services::duplicate_service,
+            services::copy_service_to_scene,
+            services::move_service,
             services::get_service_assets,
+            services::delete_asset,
+            services::rename_asset,
+            services::move_asset,
+            services::read_asset_file,
+            services::write_asset_file,
+            services::preview_service_templates,
+            services::start_watching_service_assets,
+            services::stop_watching_service_assets,
             services::update_service,
             services::start_emitting_scene_status,
             services::stop_emitting_scene_status,
+            services::get_service_health_history,
+            services::get_service_endpoints,
             services::run_service,
             services::stop_service,
             services::start_emitting_service_logs,
             services::stop_emitting_service_logs,
+            services::export_service_logs,
+            services::start_persisting_service_logs,
+            services::stop_persisting_service_logs,
+            services::get_persisted_service_log_runs,
+            services::get_persisted_service_logs,
+            services::pull_service_image,
+            services::build_service,
+            services::cancel_service_image_operation,
             dependencies::create_dependency,
             dependencies::delete_dependency,
             dependencies::set_dependency_condition,
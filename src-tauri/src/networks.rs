@@ -0,0 +1,248 @@
+use std::collections::{BTreeMap, HashMap};
+
+use bollard::{network::ListNetworksOptions, Docker};
+use serde::Serialize;
+use serde_yaml::Value;
+
+use crate::{docker, scenes};
+
+#[derive(Serialize, Clone)]
+pub struct NetworkContainer {
+    #[serde(rename = "containerName")]
+    pub container_name: String,
+    #[serde(rename = "serviceId")]
+    pub service_id: String,
+    pub replica: u32,
+    #[serde(rename = "ipAddress")]
+    pub ip_address: Option<String>,
+    #[serde(rename = "ipv6Address")]
+    pub ipv6_address: Option<String>,
+    pub aliases: Vec<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct SceneNetwork {
+    pub name: String,
+    /// Key of the network in the `networks` section, missing for networks
+    /// the containers are attached to without being declared in the file
+    #[serde(rename = "composeName")]
+    pub compose_name: Option<String>,
+    pub driver: Option<String>,
+    pub external: bool,
+    /// Whether the network exists in docker, external networks and networks
+    /// of a scene that was never run are declared without existing
+    pub exists: bool,
+    pub containers: Vec<NetworkContainer>,
+}
+
+#[derive(Serialize)]
+pub struct SceneNetworks {
+    pub networks: Vec<SceneNetwork>,
+    pub warnings: Vec<String>,
+}
+
+struct DeclaredNetwork {
+    compose_name: String,
+    name: String,
+    external: bool,
+}
+
+/// Reads the top-level `networks` section of a compose file, resolving the
+/// name docker knows each network by
+fn get_declared_networks(project_name: &str, networks: Option<&Value>) -> Vec<DeclaredNetwork> {
+    let Some(Value::Mapping(networks)) = networks else {
+        return vec![];
+    };
+
+    networks
+        .iter()
+        .filter_map(|(compose_name, network)| {
+            let compose_name = compose_name.as_str()?.to_string();
+            let external = match network.get("external") {
+                Some(Value::Bool(external)) => *external,
+                // Legacy `external: { name: ... }` syntax
+                Some(Value::Mapping(_)) => true,
+                _ => false,
+            };
+            let explicit_name = network
+                .get("name")
+                .or_else(|| {
+                    network
+                        .get("external")
+                        .and_then(|external| external.get("name"))
+                })
+                .and_then(Value::as_str)
+                .map(|name| name.to_string());
+
+            let name = match (explicit_name, external) {
+                (Some(name), _) => name,
+                (None, true) => compose_name.clone(),
+                (None, false) => format!("{project_name}_{compose_name}"),
+            };
+
+            Some(DeclaredNetwork {
+                compose_name,
+                name,
+                external,
+            })
+        })
+        .collect()
+}
+
+/// Lists the keys of the networks a service is attached to, in either the
+/// list or the mapping syntax
+fn get_service_network_names(networks: Option<&Value>) -> Vec<String> {
+    match networks {
+        Some(Value::Sequence(networks)) => networks
+            .iter()
+            .filter_map(|network| network.as_str().map(|network| network.to_string()))
+            .collect(),
+        Some(Value::Mapping(networks)) => networks
+            .keys()
+            .filter_map(|network| network.as_str().map(|network| network.to_string()))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Lists the networks of the scene, declared in its compose files or created
+/// by compose for its project, with the containers attached to each of them
+pub async fn get_scene_networks(scene_name: &str) -> Result<SceneNetworks, String> {
+    let docker = Docker::connect_with_socket_defaults()
+        .map_err(|error| format!("Cannot connect to docker socket: {}", error))?;
+
+    let project_name = docker::get_compose_project_name(scene_name);
+
+    // Included scenes declare their networks in their own compose file
+    let mut file_scene_names = vec![scene_name.to_string()];
+    for service in scenes::read_scene_services(scene_name)? {
+        if !file_scene_names.contains(&service.scene_name) {
+            file_scene_names.push(service.scene_name);
+        }
+    }
+
+    let mut networks: BTreeMap<String, SceneNetwork> = BTreeMap::new();
+    let mut warnings = vec![];
+    for file_scene_name in &file_scene_names {
+        let docker_compose_file = docker::get_docker_compose_file(file_scene_name)?;
+        let declared_networks =
+            get_declared_networks(&project_name, docker_compose_file.extra.get("networks"));
+
+        let mut service_ids = docker_compose_file.services.keys().collect::<Vec<_>>();
+        service_ids.sort();
+        for service_id in service_ids {
+            let service = &docker_compose_file.services[service_id];
+            for network_name in get_service_network_names(service.extra.get("networks")) {
+                // The default network is implicitly declared
+                let is_declared = network_name == "default"
+                    || declared_networks
+                        .iter()
+                        .any(|network| network.compose_name == network_name);
+                if !is_declared {
+                    warnings.push(format!(
+                        "Service {service_id} of scene {file_scene_name} references network {network_name} which is not declared in the networks section"
+                    ));
+                }
+            }
+        }
+
+        for declared_network in declared_networks {
+            networks.insert(
+                declared_network.name.clone(),
+                SceneNetwork {
+                    name: declared_network.name,
+                    compose_name: Some(declared_network.compose_name),
+                    driver: None,
+                    external: declared_network.external,
+                    exists: false,
+                    containers: vec![],
+                },
+            );
+        }
+    }
+
+    let project_networks = docker
+        .list_networks(Some(ListNetworksOptions {
+            filters: HashMap::from([(
+                "label".to_string(),
+                vec![format!("com.docker.compose.project={project_name}")],
+            )]),
+        }))
+        .await
+        .map_err(|err| format!("Cannot list networks for scene {scene_name}: {err}"))?;
+
+    for project_network in project_networks {
+        let Some(name) = project_network.name else {
+            continue;
+        };
+        let compose_name = project_network
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.get("com.docker.compose.network").cloned());
+
+        let network = networks.entry(name.clone()).or_insert(SceneNetwork {
+            name,
+            compose_name,
+            driver: None,
+            external: false,
+            exists: true,
+            containers: vec![],
+        });
+        network.driver = project_network.driver;
+        network.exists = true;
+    }
+
+    for network in networks.values_mut().filter(|network| !network.exists) {
+        if let Ok(inspect) = docker.inspect_network::<String>(&network.name, None).await {
+            network.driver = inspect.driver;
+            network.exists = true;
+        } else if network.external {
+            warnings.push(format!(
+                "External network {} does not exist, it must be created before running the scene",
+                network.name
+            ));
+        }
+    }
+
+    for container in docker::get_scene_containers(&docker, scene_name).await? {
+        let inspect = docker
+            .inspect_container(&container.id, None)
+            .await
+            .map_err(|err| format!("Cannot inspect container {}: {err}", container.name))?;
+
+        let container_networks = inspect
+            .network_settings
+            .and_then(|network_settings| network_settings.networks)
+            .unwrap_or_default();
+
+        for (network_name, endpoint) in container_networks {
+            let network = networks
+                .entry(network_name.clone())
+                .or_insert(SceneNetwork {
+                    name: network_name,
+                    compose_name: None,
+                    driver: None,
+                    external: false,
+                    exists: true,
+                    containers: vec![],
+                });
+
+            // Stopped containers keep their endpoint settings with empty addresses
+            network.containers.push(NetworkContainer {
+                container_name: container.name.clone(),
+                service_id: container.service_id.clone(),
+                replica: container.replica,
+                ip_address: endpoint.ip_address.filter(|address| !address.is_empty()),
+                ipv6_address: endpoint
+                    .global_ipv6_address
+                    .filter(|address| !address.is_empty()),
+                aliases: endpoint.aliases.unwrap_or_default(),
+            });
+        }
+    }
+
+    Ok(SceneNetworks {
+        networks: networks.into_values().collect(),
+        warnings,
+    })
+}
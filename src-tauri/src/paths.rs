@@ -0,0 +1,321 @@
+use std::{
+    fmt,
+    ops::Deref,
+    path::{Component, Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::get_config_dirpath;
+
+const MAX_NAME_LENGTH: usize = 255;
+
+/// Checks that a name can be used as a single folder name, which is what
+/// scene names and service ids end up being on disk
+fn validate_folder_name(kind: &str, name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err(format!("{kind} cannot be empty"));
+    }
+    if name.len() > MAX_NAME_LENGTH {
+        return Err(format!(
+            "{kind} cannot be longer than {MAX_NAME_LENGTH} characters"
+        ));
+    }
+    if name.starts_with('.') {
+        return Err(format!("{kind} {name:?} cannot start with a dot"));
+    }
+    if name
+        .chars()
+        .any(|char| char == '/' || char == '\\' || char.is_control())
+    {
+        return Err(format!(
+            "{kind} {name:?} cannot contain slashes or control characters"
+        ));
+    }
+
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(()),
+        _ => Err(format!("{kind} {name:?} is not a valid folder name")),
+    }
+}
+
+/// Name of a scene, guaranteed to be a single folder of the scenes root
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(try_from = "String")]
+pub struct SceneName(String);
+
+impl SceneName {
+    pub fn new(name: &str) -> Result<Self, String> {
+        validate_folder_name("Scene name", name)?;
+        Ok(Self(name.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn dirpath(&self) -> PathBuf {
+        get_config_dirpath().join("scenes").join(&self.0)
+    }
+}
+
+impl AsRef<Path> for SceneName {
+    fn as_ref(&self) -> &Path {
+        Path::new(&self.0)
+    }
+}
+
+impl TryFrom<String> for SceneName {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Self::new(&name)
+    }
+}
+
+impl Deref for SceneName {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl fmt::Display for SceneName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Id of a service, restricted to the characters allowed by the compose
+/// specification so that it is also a safe assets folder name
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(try_from = "String")]
+pub struct ServiceId(String);
+
+impl ServiceId {
+    pub fn new(id: &str) -> Result<Self, String> {
+        validate_folder_name("Service id", id)?;
+        if !id
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || matches!(char, '_' | '.' | '-'))
+        {
+            return Err(format!(
+                "Service id {id:?} can only contain letters, digits, dots, dashes and underscores"
+            ));
+        }
+
+        Ok(Self(id.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for ServiceId {
+    fn as_ref(&self) -> &Path {
+        Path::new(&self.0)
+    }
+}
+
+impl TryFrom<String> for ServiceId {
+    type Error = String;
+
+    fn try_from(id: String) -> Result<Self, Self::Error> {
+        Self::new(&id)
+    }
+}
+
+impl Deref for ServiceId {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl fmt::Display for ServiceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Path of an asset relative to the assets folder of a service. It cannot
+/// be absolute nor contain parent components, symlinks are checked when the
+/// path is resolved against the assets folder.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct AssetPath(PathBuf);
+
+impl AssetPath {
+    pub fn new(path: &str) -> Result<Self, String> {
+        if path.contains('\0') {
+            return Err(format!("Invalid asset path {path:?}"));
+        }
+
+        let mut normalized_path = PathBuf::new();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(component) => normalized_path.push(component),
+                Component::CurDir => {}
+                _ => {
+                    return Err(format!(
+                    "Invalid asset path {path:?}, it must be relative to the service assets folder"
+                ))
+                }
+            }
+        }
+
+        if normalized_path.as_os_str().is_empty() {
+            return Err(format!("Invalid asset path {path:?}"));
+        }
+
+        Ok(Self(normalized_path))
+    }
+}
+
+impl AsRef<Path> for AssetPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for AssetPath {
+    type Error = String;
+
+    fn try_from(path: String) -> Result<Self, Self::Error> {
+        Self::new(&path)
+    }
+}
+
+impl From<AssetPath> for String {
+    fn from(path: AssetPath) -> Self {
+        path.0.to_string_lossy().to_string()
+    }
+}
+
+impl Deref for AssetPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl fmt::Display for AssetPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.display())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOSTILE_NAMES: [&str; 16] = [
+        "",
+        ".",
+        "..",
+        "../scenes",
+        "../../etc",
+        "/",
+        "/etc",
+        "scene/..",
+        "scene/service",
+        "..\\..\\windows",
+        "C:\\scenes",
+        "scene\0",
+        "scene\n",
+        ".hidden",
+        "~/../..",
+        "./scene",
+    ];
+
+    #[test]
+    fn scene_name_rejects_hostile_inputs() {
+        for name in HOSTILE_NAMES {
+            assert!(SceneName::new(name).is_err(), "{name:?} was accepted");
+        }
+        assert!(SceneName::new(&"a".repeat(MAX_NAME_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn scene_name_accepts_folder_names() {
+        for name in ["dev", "My Scene", "scene-1_2.3", "émoji-🚀"] {
+            assert_eq!(&*SceneName::new(name).unwrap(), name);
+        }
+    }
+
+    #[test]
+    fn scene_name_dirpath_stays_in_scenes_root() {
+        let scene_name = SceneName::new("dev").unwrap();
+        assert_eq!(
+            scene_name.dirpath(),
+            get_config_dirpath().join("scenes").join("dev")
+        );
+    }
+
+    #[test]
+    fn service_id_rejects_hostile_inputs() {
+        for id in HOSTILE_NAMES {
+            assert!(ServiceId::new(id).is_err(), "{id:?} was accepted");
+        }
+        for id in ["web server", "db:5432", "$(rm -rf)", "api;ls", "émoji"] {
+            assert!(ServiceId::new(id).is_err(), "{id:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn service_id_accepts_compose_names() {
+        for id in ["db", "web-1", "api_v2", "cache.redis", "0"] {
+            assert_eq!(&*ServiceId::new(id).unwrap(), id);
+        }
+    }
+
+    #[test]
+    fn asset_path_rejects_hostile_inputs() {
+        for path in [
+            "",
+            ".",
+            "./",
+            "..",
+            "../secrets",
+            "conf/../../secrets",
+            "conf/..",
+            "/etc/passwd",
+            "//etc",
+            "conf\0.txt",
+        ] {
+            assert!(AssetPath::new(path).is_err(), "{path:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn asset_path_normalizes_relative_paths() {
+        for (path, normalized_path) in [
+            ("nginx.conf", "nginx.conf"),
+            ("./conf/nginx.conf", "conf/nginx.conf"),
+            ("conf//nginx.conf", "conf/nginx.conf"),
+            ("conf/./nginx.conf", "conf/nginx.conf"),
+            ("conf/", "conf"),
+        ] {
+            assert_eq!(
+                AssetPath::new(path).unwrap().as_os_str(),
+                normalized_path,
+                "{path:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn deserialization_validates_inputs() {
+        assert!(serde_json::from_str::<SceneName>("\"..\"").is_err());
+        assert!(serde_json::from_str::<ServiceId>("\"../db\"").is_err());
+        assert!(serde_json::from_str::<AssetPath>("\"../../.ssh/id_rsa\"").is_err());
+        assert!(serde_json::from_str::<SceneName>("\"dev\"").is_ok());
+        assert!(serde_json::from_str::<ServiceId>("\"db\"").is_ok());
+        assert!(serde_json::from_str::<AssetPath>("\"conf/nginx.conf\"").is_ok());
+    }
+}
@@ -0,0 +1,599 @@
+use std::{
+    collections::HashSet,
+    io,
+    net::{IpAddr, Ipv4Addr, TcpListener, UdpSocket},
+};
+
+use bollard::{container::ListContainersOptions, Docker};
+use serde::Serialize;
+use serde_yaml::Value;
+
+use crate::{docker, scenes};
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ServicePort {
+    #[serde(rename = "serviceId")]
+    pub service_id: String,
+    /// Missing when the port is published on every interface
+    #[serde(rename = "hostIp")]
+    pub host_ip: Option<String>,
+    /// Missing when docker picks an ephemeral host port
+    #[serde(rename = "hostPorts")]
+    pub host_ports: Option<(u16, u16)>,
+    #[serde(rename = "containerPorts")]
+    pub container_ports: (u16, u16),
+    pub protocol: String,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(tag = "type")]
+pub enum PortConflictSource {
+    /// Another service of the same scene publishes the port
+    #[serde(rename = "service")]
+    Service {
+        #[serde(rename = "serviceId")]
+        service_id: String,
+    },
+    /// A running container outside of the scene, belonging to another scene
+    /// when `project` is set
+    #[serde(rename = "container")]
+    Container {
+        #[serde(rename = "containerName")]
+        container_name: String,
+        project: Option<String>,
+        #[serde(rename = "serviceId")]
+        service_id: Option<String>,
+    },
+    /// A process of the host, outside of docker
+    #[serde(rename = "host")]
+    Host,
+}
+
+#[derive(Serialize, Clone)]
+pub struct PortConflict {
+    #[serde(rename = "serviceId")]
+    pub service_id: String,
+    #[serde(rename = "hostIp")]
+    pub host_ip: Option<String>,
+    #[serde(rename = "hostPort")]
+    pub host_port: u16,
+    pub protocol: String,
+    pub source: PortConflictSource,
+}
+
+impl PortConflict {
+    fn describe(&self) -> String {
+        let port = format!("{}/{}", self.host_port, self.protocol);
+        match &self.source {
+            PortConflictSource::Service { service_id } => format!(
+                "Port {port} of service {} is also published by service {service_id}",
+                self.service_id
+            ),
+            PortConflictSource::Container {
+                container_name,
+                project: Some(project),
+                ..
+            } => format!(
+                "Port {port} of service {} is already used by container {container_name} of scene {project}",
+                self.service_id
+            ),
+            PortConflictSource::Container { container_name, .. } => format!(
+                "Port {port} of service {} is already used by container {container_name}",
+                self.service_id
+            ),
+            PortConflictSource::Host => format!(
+                "Port {port} of service {} is already used by another process",
+                self.service_id
+            ),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PortConflictReport {
+    pub ports: Vec<ServicePort>,
+    pub conflicts: Vec<PortConflict>,
+    /// Port definitions that could not be checked, such as ones relying on
+    /// variable interpolation
+    pub warnings: Vec<String>,
+}
+
+fn parse_port_range(range: &str) -> Option<(u16, u16)> {
+    match range.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+            (start <= end).then_some((start, end))
+        }
+        None => range.parse().ok().map(|port| (port, port)),
+    }
+}
+
+/// Parses the `[HOST_IP:][HOST_PORTS:]CONTAINER_PORTS[/PROTOCOL]` short syntax,
+/// where IPv6 host addresses may be enclosed in brackets
+fn parse_short_port(service_id: &str, port: &str) -> Option<ServicePort> {
+    let (mapping, protocol) = port.split_once('/').unwrap_or((port, "tcp"));
+
+    let (host_ip, ports) = match mapping.strip_prefix('[') {
+        Some(mapping) => {
+            let (host_ip, ports) = mapping.split_once("]:")?;
+            (
+                Some(host_ip.to_string()),
+                ports.split(':').collect::<Vec<_>>(),
+            )
+        }
+        None => {
+            let mut parts = mapping.rsplitn(3, ':').collect::<Vec<_>>();
+            parts.reverse();
+            match parts.len() {
+                3 => (Some(parts[0].to_string()), parts[1..].to_vec()),
+                _ => (None, parts),
+            }
+        }
+    };
+
+    let (host_ports, container_ports) = match ports[..] {
+        [container_ports] => (None, container_ports),
+        ["", container_ports] => (None, container_ports),
+        [host_ports, container_ports] => (Some(parse_port_range(host_ports)?), container_ports),
+        _ => return None,
+    };
+
+    Some(ServicePort {
+        service_id: service_id.to_string(),
+        host_ip: host_ip.filter(|host_ip| !host_ip.is_empty()),
+        host_ports,
+        container_ports: parse_port_range(container_ports)?,
+        protocol: protocol.to_lowercase(),
+    })
+}
+
+fn parse_long_port(service_id: &str, port: &Value) -> Option<ServicePort> {
+    let get_string = |key: &str| match port.get(key)? {
+        Value::String(value) => Some(value.to_string()),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    };
+
+    let host_ports = match get_string("published") {
+        Some(published) => Some(parse_port_range(&published)?),
+        None => None,
+    };
+
+    Some(ServicePort {
+        service_id: service_id.to_string(),
+        host_ip: get_string("host_ip").filter(|host_ip| !host_ip.is_empty()),
+        host_ports,
+        container_ports: parse_port_range(&get_string("target")?)?,
+        protocol: get_string("protocol")
+            .unwrap_or("tcp".to_string())
+            .to_lowercase(),
+    })
+}
+
+/// Reads the `ports` section of a service, returning the ports that could
+/// not be parsed alongside
+pub fn parse_service_ports(
+    service_id: &str,
+    ports: Option<&Value>,
+) -> (Vec<ServicePort>, Vec<String>) {
+    let Some(Value::Sequence(ports)) = ports else {
+        return (vec![], vec![]);
+    };
+
+    let mut service_ports = vec![];
+    let mut invalid_ports = vec![];
+    for port in ports {
+        let service_port = match port {
+            Value::Number(port) => parse_short_port(service_id, &port.to_string()),
+            Value::String(port) => parse_short_port(service_id, port),
+            Value::Mapping(_) => parse_long_port(service_id, port),
+            _ => None,
+        };
+
+        match service_port {
+            Some(service_port) => service_ports.push(service_port),
+            None => invalid_ports.push(serde_yaml::to_string(port).unwrap().trim().to_string()),
+        }
+    }
+
+    (service_ports, invalid_ports)
+}
+
+/// Unspecified addresses listen on every interface, so they overlap with any
+/// other address
+fn host_ips_overlap(host_ip: Option<&str>, other_host_ip: Option<&str>) -> bool {
+    let is_unspecified = |host_ip: Option<&str>| {
+        host_ip.is_none_or(|host_ip| {
+            host_ip
+                .parse::<IpAddr>()
+                .is_ok_and(|host_ip| host_ip.is_unspecified())
+        })
+    };
+
+    is_unspecified(host_ip) || is_unspecified(other_host_ip) || host_ip == other_host_ip
+}
+
+/// Binds the host port to find out whether a host process already uses it.
+/// Only `AddrInUse` tells so, other errors such as missing privileges for
+/// ports below 1024 or a non local address are returned since docker may
+/// still bind the port.
+fn is_host_port_free(host_ip: Option<&str>, host_port: u16, protocol: &str) -> io::Result<bool> {
+    let host_ip = host_ip
+        .and_then(|host_ip| host_ip.parse::<IpAddr>().ok())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+
+    let result = match protocol {
+        "udp" => UdpSocket::bind((host_ip, host_port)).map(|_| ()),
+        _ => TcpListener::bind((host_ip, host_port)).map(|_| ()),
+    };
+    match result {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::AddrInUse => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Finds a port of another service publishing the same host port. Only the
+/// active services are considered, the ones being started and the ones with
+/// running containers, stopped services do not hold their ports.
+fn find_service_conflict<'a>(
+    ports: &'a [ServicePort],
+    port: &ServicePort,
+    host_port: u16,
+    active_service_ids: &HashSet<&str>,
+) -> Option<&'a ServicePort> {
+    ports.iter().find(|other_port| {
+        other_port.service_id != port.service_id
+            && active_service_ids.contains(other_port.service_id.as_str())
+            && other_port.protocol == port.protocol
+            && other_port
+                .host_ports
+                .is_some_and(|(start, end)| (start..=end).contains(&host_port))
+            && host_ips_overlap(port.host_ip.as_deref(), other_port.host_ip.as_deref())
+    })
+}
+
+struct BoundPort {
+    container_name: String,
+    project: Option<String>,
+    service_id: Option<String>,
+    host_ip: Option<String>,
+    host_port: u16,
+    protocol: String,
+}
+
+/// Lists the host ports published by the running containers
+async fn get_bound_ports(docker: &Docker) -> Result<Vec<BoundPort>, String> {
+    let containers = docker
+        .list_containers(Some(ListContainersOptions::<String>::default()))
+        .await
+        .map_err(|err| format!("Cannot list containers: {err}"))?;
+
+    let mut bound_ports = vec![];
+    for container in containers {
+        let labels = container.labels.unwrap_or_default();
+        let project = labels.get("com.docker.compose.project").cloned();
+
+        let container_name = container
+            .names
+            .and_then(|names| names.first().cloned())
+            .map(|name| name.trim_start_matches('/').to_string())
+            .unwrap_or_default();
+
+        for port in container.ports.unwrap_or_default() {
+            let Some(host_port) = port.public_port else {
+                continue;
+            };
+
+            bound_ports.push(BoundPort {
+                container_name: container_name.clone(),
+                project: project.clone(),
+                service_id: labels.get("com.docker.compose.service").cloned(),
+                host_ip: port.ip,
+                host_port,
+                protocol: port
+                    .typ
+                    .map(|typ| typ.to_string())
+                    .filter(|typ| !typ.is_empty())
+                    .unwrap_or("tcp".to_string()),
+            });
+        }
+    }
+
+    Ok(bound_ports)
+}
+
+/// Checks that the host ports published by the services of the scene, or by
+/// a single one of them, are not published twice by the services being
+/// started and the running ones, nor already used by other containers or host
+/// processes. Ports published by the scene's own running containers are not
+/// reported since compose reuses them.
+pub async fn get_scene_port_conflicts(
+    scene_name: &str,
+    service_id: Option<&str>,
+) -> Result<PortConflictReport, String> {
+    let docker = Docker::connect_with_socket_defaults()
+        .map_err(|error| format!("Cannot connect to docker socket: {}", error))?;
+
+    let mut ports = vec![];
+    let mut warnings = vec![];
+    for service in scenes::read_scene_services(scene_name)? {
+        let service_ports = docker::get_docker_compose_file(&service.scene_name)?
+            .services
+            .get(&service.id)
+            .map(|definition| parse_service_ports(&service.id, definition.extra.get("ports")));
+        let Some((service_ports, invalid_ports)) = service_ports else {
+            continue;
+        };
+
+        for invalid_port in invalid_ports {
+            warnings.push(format!(
+                "Cannot check port {invalid_port} of service {}",
+                service.id
+            ));
+        }
+        ports.extend(service_ports);
+    }
+
+    let project_name = docker::get_compose_project_name(scene_name);
+    let (own_bound_ports, bound_ports): (Vec<_>, Vec<_>) = get_bound_ports(&docker)
+        .await?
+        .into_iter()
+        .partition(|bound_port| bound_port.project.as_deref() == Some(project_name.as_str()));
+
+    let is_checked = |port: &ServicePort| match service_id {
+        Some(service_id) => port.service_id == service_id,
+        None => true,
+    };
+    let active_service_ids = ports
+        .iter()
+        .filter(|port| is_checked(port))
+        .map(|port| port.service_id.as_str())
+        .chain(
+            own_bound_ports
+                .iter()
+                .filter_map(|bound_port| bound_port.service_id.as_deref()),
+        )
+        .collect::<HashSet<_>>();
+
+    let mut conflicts = vec![];
+    let checked_ports = ports.iter().filter(|port| is_checked(port));
+    for port in checked_ports {
+        let Some((host_port_start, host_port_end)) = port.host_ports else {
+            continue;
+        };
+
+        for host_port in host_port_start..=host_port_end {
+            let mut add_conflict = |source: PortConflictSource| {
+                conflicts.push(PortConflict {
+                    service_id: port.service_id.clone(),
+                    host_ip: port.host_ip.clone(),
+                    host_port,
+                    protocol: port.protocol.clone(),
+                    source,
+                })
+            };
+
+            if let Some(other_service) =
+                find_service_conflict(&ports, port, host_port, &active_service_ids)
+            {
+                add_conflict(PortConflictSource::Service {
+                    service_id: other_service.service_id.clone(),
+                });
+                continue;
+            }
+
+            let is_bound_by = |bound_port: &&BoundPort| {
+                bound_port.host_port == host_port
+                    && bound_port.protocol == port.protocol
+                    && host_ips_overlap(port.host_ip.as_deref(), bound_port.host_ip.as_deref())
+            };
+            if let Some(bound_port) = bound_ports.iter().find(is_bound_by) {
+                add_conflict(PortConflictSource::Container {
+                    container_name: bound_port.container_name.clone(),
+                    project: bound_port.project.clone(),
+                    service_id: bound_port.service_id.clone(),
+                });
+                continue;
+            }
+
+            // The scene's own containers hold their ports while running
+            if own_bound_ports
+                .iter()
+                .any(|bound_port| is_bound_by(&bound_port))
+            {
+                continue;
+            }
+            match is_host_port_free(port.host_ip.as_deref(), host_port, &port.protocol) {
+                Ok(true) => {}
+                Ok(false) => add_conflict(PortConflictSource::Host),
+                Err(err) => warnings.push(format!(
+                    "Cannot check whether port {host_port}/{} of service {} is free: {err}",
+                    port.protocol, port.service_id
+                )),
+            }
+        }
+    }
+
+    Ok(PortConflictReport {
+        ports,
+        conflicts,
+        warnings,
+    })
+}
+
+/// Fails with a readable summary when the services about to be started
+/// publish host ports that are already in use
+pub async fn check_scene_port_conflicts(
+    scene_name: &str,
+    service_id: Option<&str>,
+) -> Result<(), String> {
+    let report = get_scene_port_conflicts(scene_name, service_id).await?;
+    if report.conflicts.is_empty() {
+        return Ok(());
+    }
+
+    Err(format!(
+        "Cannot start scene {scene_name}, some host ports are already in use:\n{}",
+        report
+            .conflicts
+            .iter()
+            .map(PortConflict::describe)
+            .collect::<Vec<_>>()
+            .join("\n")
+    ))
+}
+
+/// Label of a service holding the path its published HTTP ports are served
+/// on, setting it is what makes the endpoints openable in a browser
+const URL_PATH_LABEL: &str = "workbench.url-path";
+
+#[derive(Serialize)]
+pub struct ServiceEndpoint {
+    pub replica: u32,
+    #[serde(rename = "containerPort")]
+    pub container_port: u16,
+    pub protocol: String,
+    #[serde(rename = "hostIp")]
+    pub host_ip: String,
+    #[serde(rename = "hostPort")]
+    pub host_port: u16,
+    pub url: Option<String>,
+}
+
+fn get_endpoint_url(host_ip: &str, host_port: u16, url_path: &str) -> String {
+    let host = match host_ip.parse::<IpAddr>() {
+        Ok(host_ip) if host_ip.is_unspecified() => "localhost".to_string(),
+        Ok(IpAddr::V6(host_ip)) => format!("[{host_ip}]"),
+        _ => host_ip.to_string(),
+    };
+    let separator = if url_path.starts_with('/') { "" } else { "/" };
+
+    format!("http://{host}:{host_port}{separator}{url_path}")
+}
+
+/// Reads the host ports actually bound for the containers of a service,
+/// including the ephemeral ones picked by docker
+pub async fn get_service_endpoints(
+    scene_name: &str,
+    service_id: &str,
+) -> Result<Vec<ServiceEndpoint>, String> {
+    let docker = Docker::connect_with_socket_defaults()
+        .map_err(|error| format!("Cannot connect to docker socket: {}", error))?;
+
+    let containers = docker::get_scene_containers(&docker, scene_name)
+        .await?
+        .into_iter()
+        .filter(|container| container.service_id == service_id);
+
+    let mut endpoints = vec![];
+    for container in containers {
+        let inspect = docker
+            .inspect_container(&container.id, None)
+            .await
+            .map_err(|err| format!("Cannot inspect container {}: {err}", container.name))?;
+
+        let url_path = inspect
+            .config
+            .and_then(|config| config.labels)
+            .and_then(|labels| labels.get(URL_PATH_LABEL).cloned());
+        let port_bindings = inspect
+            .network_settings
+            .and_then(|network_settings| network_settings.ports)
+            .unwrap_or_default();
+
+        for (container_port, bindings) in port_bindings {
+            let (container_port, protocol) = container_port
+                .split_once('/')
+                .unwrap_or((&container_port, "tcp"));
+            let Ok(container_port) = container_port.parse::<u16>() else {
+                continue;
+            };
+
+            // Exposed ports that are not published have no bindings
+            for binding in bindings.unwrap_or_default() {
+                let Some(host_port) = binding
+                    .host_port
+                    .and_then(|host_port| host_port.parse::<u16>().ok())
+                else {
+                    continue;
+                };
+                let host_ip = binding
+                    .host_ip
+                    .filter(|host_ip| !host_ip.is_empty())
+                    .unwrap_or(Ipv4Addr::UNSPECIFIED.to_string());
+
+                endpoints.push(ServiceEndpoint {
+                    replica: container.replica,
+                    container_port,
+                    protocol: protocol.to_string(),
+                    url: url_path
+                        .as_ref()
+                        .filter(|_| protocol == "tcp")
+                        .map(|url_path| get_endpoint_url(&host_ip, host_port, url_path)),
+                    host_ip,
+                    host_port,
+                });
+            }
+        }
+    }
+
+    endpoints.sort_by_key(|endpoint| {
+        (
+            endpoint.replica,
+            endpoint.container_port,
+            endpoint.host_port,
+        )
+    });
+
+    Ok(endpoints)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_port(service_id: &str, port: &str) -> ServicePort {
+        parse_short_port(service_id, port).unwrap()
+    }
+
+    #[test]
+    fn stopped_duplicates_do_not_conflict() {
+        let ports = [get_port("web", "8080:80"), get_port("web-copy", "8080:80")];
+
+        let active_service_ids = HashSet::from(["web"]);
+        assert_eq!(
+            find_service_conflict(&ports, &ports[0], 8080, &active_service_ids),
+            None
+        );
+
+        // Running or started along, the duplicate holds the port
+        let active_service_ids = HashSet::from(["web", "web-copy"]);
+        assert_eq!(
+            find_service_conflict(&ports, &ports[0], 8080, &active_service_ids),
+            Some(&ports[1])
+        );
+    }
+
+    #[test]
+    fn service_conflicts_match_protocol_and_host_ip() {
+        let ports = [
+            get_port("web", "127.0.0.1:8080:80"),
+            get_port("dns", "8080:53/udp"),
+            get_port("api", "127.0.0.2:8080:80"),
+            get_port("admin", "8079-8081:80"),
+        ];
+        let active_service_ids = HashSet::from(["web", "dns", "api"]);
+
+        assert_eq!(
+            find_service_conflict(&ports, &ports[0], 8080, &active_service_ids),
+            None
+        );
+
+        let active_service_ids = HashSet::from(["web", "admin"]);
+        assert_eq!(
+            find_service_conflict(&ports, &ports[0], 8080, &active_service_ids),
+            Some(&ports[3])
+        );
+    }
+}
@@ -1,19 +1,31 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fs,
-    path::PathBuf,
 };
 
 use path_absolutize::Absolutize;
 use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State};
 
 use crate::{
     docker::{
         self, DockerComposeIncludeEnum, DockerComposeIncludeObject,
         DockerComposeIncludeStringOrList,
     },
+    history::{self, SceneHistory},
+    images::{self, ServiceImageUpdate},
+    log_parsing::LogLevel,
+    logs,
+    networks::{self, SceneNetworks},
+    paths::SceneName,
+    ports::{self, PortConflictReport},
     services::Service,
+    snapshots::{self, SceneSnapshot, SnapshotDiff},
+    state::AppState,
+    stats, templates,
     utils::get_config_dirpath,
+    volumes::{self, SceneVolume},
+    watchers,
 };
 
 #[derive(Deserialize, Serialize)]
@@ -40,7 +52,12 @@ pub fn get_scenes() -> Result<Vec<Scene>, String> {
 }
 
 #[tauri::command(async)]
-pub fn get_included_scenes(scene_name: &str) -> Result<Vec<Scene>, String> {
+pub fn get_included_scenes(scene_name: SceneName) -> Result<Vec<Scene>, String> {
+    read_included_scenes(&scene_name)
+}
+
+/// Lists the scenes directly included by the scene
+pub fn read_included_scenes(scene_name: &str) -> Result<Vec<Scene>, String> {
     let docker_compose = docker::get_docker_compose_file(scene_name)?;
     let include = match docker_compose.include {
         Some(x) => x,
@@ -87,29 +104,70 @@ pub fn get_included_scenes(scene_name: &str) -> Result<Vec<Scene>, String> {
 }
 
 #[tauri::command(async)]
-pub fn create_scene(scene_name: &str) -> Result<(), String> {
-    let (scene_name_length, _) = PathBuf::from(scene_name).iter().size_hint();
-    if scene_name_length > 1 {
-        return Err("Cannot create a scene within other folders".to_string());
-    }
-
-    let scene_path = get_config_dirpath().join("scenes").join(scene_name);
-    fs_extra::dir::create(&scene_path, false)
-        .map_err(|err| format!("Could not create scene folder {scene_name}: {err}"))?;
-
-    let docker_compose_path = scene_path.join("docker-compose.yml");
-    fs::write(docker_compose_path, "services: {}")
-        .map_err(|err| format!("Could not create docker-compose.yml for scene {scene_name}: {err}"))
+pub fn create_scene(state: State<'_, AppState>, scene_name: SceneName) -> Result<(), String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    history::record_scene_edit(
+        &scene_name,
+        &format!("Create scene {scene_name}"),
+        &[history::scene_path(&scene_name)],
+        || {
+            let scene_path = scene_name.dirpath();
+            fs_extra::dir::create(&scene_path, false)
+                .map_err(|err| format!("Could not create scene folder {scene_name}: {err}"))?;
+
+            let docker_compose_path = scene_path.join("docker-compose.yml");
+            let docker_compose = "services: {}";
+            fs::write(docker_compose_path, docker_compose).map_err(|err| {
+                format!("Could not create docker-compose.yml for scene {scene_name}: {err}")
+            })?;
+            docker::record_docker_compose_file_write(
+                &scene_name,
+                None,
+                docker::get_content_hash(docker_compose),
+            );
+            Ok(())
+        },
+    )
 }
 
 #[tauri::command(async)]
-pub fn delete_scene(scene_name: &str) -> Result<(), String> {
-    let scene_path = get_config_dirpath().join("scenes").join(scene_name);
-    fs::remove_dir_all(scene_path).map_err(|err| format!("Could not delete scene: {err}"))
+pub fn delete_scene(state: State<'_, AppState>, scene_name: SceneName) -> Result<(), String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    // Not recorded in the history, restoring the snapshot brings the scene back
+    snapshots::take_scene_snapshot(
+        &scene_name,
+        &format!("Before deleting scene {scene_name}"),
+        false,
+    )?;
+    snapshots::trash_scene(&scene_name)
 }
 
 #[tauri::command(async)]
-pub fn detach_scene(scene_name: &str, scene_name_to_detach: &str) -> Result<(), String> {
+pub fn detach_scene(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    scene_name_to_detach: SceneName,
+    compose_file_hash: Option<String>,
+) -> Result<(), String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    docker::ensure_docker_compose_file_is_unchanged(&scene_name, compose_file_hash.as_deref())?;
+    snapshots::take_scene_snapshot(
+        &scene_name,
+        &format!("Before detaching scene {scene_name_to_detach}"),
+        false,
+    )?;
+    history::record_scene_edit(
+        &scene_name,
+        &format!("Detach scene {scene_name_to_detach}"),
+        &[history::compose_file_path(&scene_name)],
+        || remove_included_scene(&scene_name, &scene_name_to_detach),
+    )
+}
+
+fn remove_included_scene(
+    scene_name: &SceneName,
+    scene_name_to_detach: &SceneName,
+) -> Result<(), String> {
     let mut docker_compose = docker::get_docker_compose_file(scene_name)?;
     if let Some(include) = docker_compose.include {
         let include = include
@@ -143,7 +201,7 @@ pub fn detach_scene(scene_name: &str, scene_name_to_detach: &str) -> Result<(),
                             .to_str()
                             .unwrap();
 
-                        if scene_name != scene_name_to_detach {
+                        if scene_name != scene_name_to_detach.as_str() {
                             acc.push(include_item);
                         }
                     }
@@ -156,11 +214,30 @@ pub fn detach_scene(scene_name: &str, scene_name_to_detach: &str) -> Result<(),
         docker_compose.include = Some(include);
     }
 
-    docker::write_docker_compose_file(scene_name, &docker_compose)
+    docker::write_docker_compose_file(scene_name, &mut docker_compose)
 }
 
 #[tauri::command(async)]
-pub fn import_scene(scene_name: &str, scene_name_to_import: &str) -> Result<(), String> {
+pub fn import_scene(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    scene_name_to_import: SceneName,
+    compose_file_hash: Option<String>,
+) -> Result<(), String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    docker::ensure_docker_compose_file_is_unchanged(&scene_name, compose_file_hash.as_deref())?;
+    history::record_scene_edit(
+        &scene_name,
+        &format!("Import scene {scene_name_to_import}"),
+        &[history::compose_file_path(&scene_name)],
+        || add_included_scene(&scene_name, &scene_name_to_import),
+    )
+}
+
+fn add_included_scene(
+    scene_name: &SceneName,
+    scene_name_to_import: &SceneName,
+) -> Result<(), String> {
     let mut docker_compose = docker::get_docker_compose_file(scene_name)?;
     let mut include = match docker_compose.include {
         Some(x) => x,
@@ -195,12 +272,18 @@ pub fn import_scene(scene_name: &str, scene_name_to_import: &str) -> Result<(),
     ));
 
     docker_compose.include = Some(include);
-    docker::write_docker_compose_file(scene_name, &docker_compose)
+    docker::write_docker_compose_file(scene_name, &mut docker_compose)
 }
 
 #[tauri::command(async)]
-pub fn get_scene_services(scene_name: &str) -> Result<Vec<Service>, String> {
+pub fn get_scene_services(scene_name: SceneName) -> Result<Vec<Service>, String> {
+    read_scene_services(&scene_name)
+}
+
+/// Lists the services of the scene and of the scenes it includes
+pub fn read_scene_services(scene_name: &str) -> Result<Vec<Service>, String> {
     let docker_compose_file = docker::get_docker_compose_file(scene_name)?;
+    let compose_file_hash = docker_compose_file.source_hash.clone().unwrap_or_default();
     let mut services: Vec<Service> = vec![];
     for (service_id, service) in docker_compose_file.services {
         services.push(Service {
@@ -216,6 +299,7 @@ pub fn get_scene_services(scene_name: &str) -> Result<Vec<Service>, String> {
                 .map(|depends_on| (depends_on.0, depends_on.1.into()))
                 .collect(),
             scene_name: scene_name.to_string(),
+            compose_file_hash: compose_file_hash.clone(),
         });
     }
 
@@ -250,7 +334,7 @@ pub fn get_scene_services(scene_name: &str) -> Result<Vec<Service>, String> {
                     .to_str()
                     .unwrap();
 
-                let external_services = get_scene_services(scene_name)?;
+                let external_services = read_scene_services(scene_name)?;
                 services = services
                     .into_iter()
                     .chain(external_services.into_iter())
@@ -263,11 +347,210 @@ pub fn get_scene_services(scene_name: &str) -> Result<Vec<Service>, String> {
 }
 
 #[tauri::command(async)]
-pub fn run_scene(scene_name: &str) -> Result<(), String> {
-    docker::run_docker_compose_up(scene_name, None)
+pub async fn run_scene(state: State<'_, AppState>, scene_name: SceneName) -> Result<(), String> {
+    ports::check_scene_port_conflicts(&scene_name, None).await?;
+
+    // Rendering and `compose up` block until done, away from the async workers
+    let scene_write_locks = state.scene_write_locks.clone();
+    tokio::task::spawn_blocking(move || {
+        templates::render_scene_templates(&scene_write_locks, &scene_name, None)?;
+        docker::run_docker_compose_up(&scene_name, None)
+    })
+    .await
+    .map_err(|err| format!("Cannot start scene: {err}"))?
+}
+
+#[tauri::command(async)]
+pub fn stop_scene(scene_name: SceneName, remove_volumes: Option<bool>) -> Result<(), String> {
+    docker::run_docker_compose_down(&scene_name, None, remove_volumes.unwrap_or(false))
+}
+
+#[tauri::command(async)]
+pub async fn start_emitting_scene_logs(
+    app: AppHandle,
+    scene_name: SceneName,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    min_level: Option<LogLevel>,
+) -> Result<(), String> {
+    logs::start_emitting_scene_logs(&app, &scene_name, include, exclude, min_level).await
+}
+
+#[tauri::command(async)]
+pub async fn stop_emitting_scene_logs(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+) -> Result<(), String> {
+    logs::stop_emitting_scene_logs(state, &scene_name).await
+}
+
+#[tauri::command(async)]
+pub async fn start_emitting_scene_stats(
+    app: AppHandle,
+    scene_name: SceneName,
+    interval: Option<u64>,
+) -> Result<(), String> {
+    stats::start_emitting_scene_stats(&app, &scene_name, interval).await
+}
+
+#[tauri::command(async)]
+pub async fn stop_emitting_scene_stats(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+) -> Result<(), String> {
+    stats::stop_emitting_scene_stats(state, &scene_name).await
+}
+
+#[tauri::command(async)]
+pub async fn pull_scene_images(
+    app: AppHandle,
+    scene_name: SceneName,
+) -> Result<Vec<String>, String> {
+    images::pull_scene_images(&app, &scene_name).await
+}
+
+#[tauri::command(async)]
+pub async fn get_scene_image_updates(
+    scene_name: SceneName,
+) -> Result<Vec<ServiceImageUpdate>, String> {
+    images::get_scene_image_updates(&scene_name).await
+}
+
+#[tauri::command(async)]
+pub async fn get_scene_volumes(scene_name: SceneName) -> Result<Vec<SceneVolume>, String> {
+    volumes::get_scene_volumes(&scene_name).await
+}
+
+#[tauri::command(async)]
+pub async fn delete_scene_volumes(
+    scene_name: SceneName,
+    volume_names: Vec<String>,
+) -> Result<(), String> {
+    volumes::delete_scene_volumes(&scene_name, volume_names).await
+}
+
+#[tauri::command(async)]
+pub async fn get_scene_port_conflicts(scene_name: SceneName) -> Result<PortConflictReport, String> {
+    ports::get_scene_port_conflicts(&scene_name, None).await
+}
+
+#[tauri::command(async)]
+pub async fn get_scene_networks(scene_name: SceneName) -> Result<SceneNetworks, String> {
+    networks::get_scene_networks(&scene_name).await
+}
+
+#[tauri::command(async)]
+pub fn get_scene_variables(scene_name: SceneName) -> Result<BTreeMap<String, String>, String> {
+    templates::read_scene_variables(&scene_name)
+}
+
+#[tauri::command(async)]
+pub fn set_scene_variables(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    variables: BTreeMap<String, String>,
+    compose_file_hash: Option<String>,
+) -> Result<(), String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    docker::ensure_docker_compose_file_is_unchanged(&scene_name, compose_file_hash.as_deref())?;
+    history::record_scene_edit(
+        &scene_name,
+        "Edit scene variables",
+        &[history::compose_file_path(&scene_name)],
+        || templates::write_scene_variables(&scene_name, variables),
+    )
+}
+
+#[tauri::command(async)]
+pub async fn start_watching_scene(app: AppHandle, scene_name: SceneName) -> Result<(), String> {
+    watchers::start_watching_scene(&app, &scene_name).await
+}
+
+#[tauri::command(async)]
+pub async fn stop_watching_scene(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+) -> Result<(), String> {
+    watchers::stop_watching_scene(state, &scene_name).await
+}
+
+#[tauri::command(async)]
+pub fn get_scene_history(scene_name: SceneName) -> Result<SceneHistory, String> {
+    history::get_scene_history(&scene_name)
+}
+
+#[tauri::command(async)]
+pub fn undo_scene_edit(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+) -> Result<String, String> {
+    history::undo_scene_edit(&state.scene_write_locks, &scene_name)
+}
+
+#[tauri::command(async)]
+pub fn redo_scene_edit(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+) -> Result<String, String> {
+    history::redo_scene_edit(&state.scene_write_locks, &scene_name)
+}
+
+#[tauri::command(async)]
+pub fn take_scene_snapshot(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    reason: Option<String>,
+) -> Result<SceneSnapshot, String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    snapshots::take_scene_snapshot(
+        &scene_name,
+        &reason.unwrap_or("Taken on demand".to_string()),
+        true,
+    )
+}
+
+#[tauri::command(async)]
+pub fn list_snapshots(scene_name: SceneName) -> Result<Vec<SceneSnapshot>, String> {
+    snapshots::list_snapshots(&scene_name)
+}
+
+#[tauri::command(async)]
+pub fn diff_snapshot(scene_name: SceneName, snapshot_id: &str) -> Result<SnapshotDiff, String> {
+    snapshots::diff_snapshot(&scene_name, snapshot_id)
+}
+
+#[tauri::command(async)]
+pub fn restore_snapshot(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    snapshot_id: &str,
+) -> Result<(), String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    if scene_name.dirpath().exists() {
+        snapshots::take_scene_snapshot(
+            &scene_name,
+            &format!("Before restoring snapshot {snapshot_id}"),
+            false,
+        )?;
+    }
+    history::record_scene_edit(
+        &scene_name,
+        &format!("Restore snapshot {snapshot_id}"),
+        &[history::scene_path(&scene_name)],
+        || {
+            watchers::record_app_writes(&[scene_name.dirpath()], || {
+                snapshots::restore_snapshot(&scene_name, snapshot_id)
+            })
+        },
+    )
 }
 
 #[tauri::command(async)]
-pub fn stop_scene(scene_name: &str) -> Result<(), String> {
-    docker::run_docker_compose_down(scene_name, None)
+pub fn delete_snapshot(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    snapshot_id: &str,
+) -> Result<(), String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    snapshots::delete_snapshot(&scene_name, snapshot_id)
 }
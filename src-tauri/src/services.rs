@@ -1,16 +1,25 @@
 use std::{
     collections::{BTreeMap, HashMap},
     fs,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, State};
 
 use crate::{
-    docker::{self, DockerComposeDependsOn, DockerComposeService},
+    assets::{self, AssetEncoding, AssetFile, AssetPathRewrite, AssetUsage},
+    docker::{self, DockerComposeDependsOn, DockerComposeService, ServiceHealth},
+    history, images,
+    log_parsing::LogLevel,
+    log_storage::{self, PersistedLogLine, PersistedLogRun},
+    logs,
+    paths::{AssetPath, SceneName, ServiceId},
+    ports::{self, ServiceEndpoint},
+    scenes, snapshots,
     state::AppState,
-    utils::get_config_dirpath,
+    templates::{self, RenderedTemplate},
+    watchers::{self, AssetWatchAction},
 };
 
 #[derive(Deserialize, Serialize)]
@@ -35,6 +44,10 @@ pub struct Service {
     pub depends_on: HashMap<String, DependsOn>,
     #[serde(rename = "sceneName")]
     pub scene_name: String,
+    /// Hash of the compose file declaring the service, sent back by the edits
+    /// to refuse them when the file was changed outside of the app since
+    #[serde(rename = "composeFileHash")]
+    pub compose_file_hash: String,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -44,18 +57,47 @@ pub struct ServiceRelationship {
 }
 
 #[tauri::command(async)]
-pub fn get_service(scene_name: &str, service_id: &str) -> Result<DockerComposeService, String> {
-    let docker_compose_file = docker::get_docker_compose_file(scene_name)?;
-    let docker_service = docker_compose_file.services.get(service_id).ok_or(format!(
-        "Cannot find service {service_id} in docker compose file"
-    ))?;
+pub fn get_service(
+    scene_name: SceneName,
+    service_id: ServiceId,
+) -> Result<DockerComposeService, String> {
+    let docker_compose_file = docker::get_docker_compose_file(&scene_name)?;
+    let docker_service = docker_compose_file
+        .services
+        .get(service_id.as_str())
+        .ok_or(format!(
+            "Cannot find service {service_id} in docker compose file"
+        ))?;
     Ok(docker_service.clone())
 }
 
 #[tauri::command(async)]
-pub fn create_service(scene_name: &str, service_id: &str, code: &str) -> Result<(), String> {
+pub fn create_service(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    service_id: ServiceId,
+    code: &str,
+    compose_file_hash: Option<String>,
+) -> Result<(), String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    docker::ensure_docker_compose_file_is_unchanged(&scene_name, compose_file_hash.as_deref())?;
+    history::record_scene_edit(
+        &scene_name,
+        &format!("Create service {service_id}"),
+        &[
+            history::compose_file_path(&scene_name),
+            history::service_assets_path(&scene_name, &service_id),
+        ],
+        || add_service(&scene_name, &service_id, code),
+    )
+}
+
+fn add_service(scene_name: &SceneName, service_id: &ServiceId, code: &str) -> Result<(), String> {
     let mut docker_compose_file = docker::get_docker_compose_file(scene_name)?;
-    if docker_compose_file.services.contains_key(service_id) {
+    if docker_compose_file
+        .services
+        .contains_key(service_id.as_str())
+    {
         return Err(format!("Service with Id {service_id} already exists"));
     }
 
@@ -65,12 +107,9 @@ pub fn create_service(scene_name: &str, service_id: &str, code: &str) -> Result<
         .services
         .insert(service_id.to_string(), deserialized_code);
 
-    docker::write_docker_compose_file(scene_name, &docker_compose_file)?;
+    docker::write_docker_compose_file(scene_name, &mut docker_compose_file)?;
 
-    let assets_dirpath = get_config_dirpath()
-        .join("scenes")
-        .join(scene_name)
-        .join(service_id);
+    let assets_dirpath = assets::get_service_assets_dirpath(scene_name, service_id);
     fs::create_dir(&assets_dirpath).map_err(|err| {
         format!(
             "Cannot create local assets directory at {} for service {service_id} in scene {scene_name}: {err}",
@@ -81,27 +120,66 @@ pub fn create_service(scene_name: &str, service_id: &str, code: &str) -> Result<
 
 #[tauri::command(async)]
 pub fn update_service(
-    scene_name: &str,
-    service_id: &str,
-    previous_service_id: &str,
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    service_id: ServiceId,
+    previous_service_id: ServiceId,
     code: &str,
-) -> Result<(), String> {
+    compose_file_hash: Option<String>,
+) -> Result<Vec<AssetPathRewrite>, String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    docker::ensure_docker_compose_file_is_unchanged(&scene_name, compose_file_hash.as_deref())?;
+    let mut paths = vec![history::compose_file_path(&scene_name)];
+    if service_id != previous_service_id {
+        // Only renaming a service moves its assets folder
+        paths.push(history::service_assets_path(
+            &scene_name,
+            &previous_service_id,
+        ));
+        paths.push(history::service_assets_path(&scene_name, &service_id));
+    }
+    history::record_scene_edit(
+        &scene_name,
+        &format!("Edit service {service_id}"),
+        &paths,
+        || replace_service(&scene_name, &service_id, &previous_service_id, code),
+    )
+}
+
+fn replace_service(
+    scene_name: &SceneName,
+    service_id: &ServiceId,
+    previous_service_id: &ServiceId,
+    code: &str,
+) -> Result<Vec<AssetPathRewrite>, String> {
     let mut docker_compose_file = docker::get_docker_compose_file(scene_name)?;
     let previous_service = docker_compose_file
         .services
-        .remove_entry(previous_service_id);
+        .remove_entry(previous_service_id.as_str());
     if previous_service.is_none() {
         return Err(format!("Cannot find service with Id {service_id}"));
     }
 
-    let service_already_exists = docker_compose_file.services.contains_key(service_id);
+    let service_already_exists = docker_compose_file
+        .services
+        .contains_key(service_id.as_str());
     if service_already_exists {
         return Err(format!("Service with Id {service_id} already exists"));
     }
 
-    let deserialized_code = serde_yaml::from_str(code)
+    let mut deserialized_code: DockerComposeService = serde_yaml::from_str(code)
         .map_err(|err| format!("Invalid format for service {service_id} configuration: {err}"))?;
 
+    let is_renamed = service_id != previous_service_id;
+    let rewrites = match is_renamed {
+        true => assets::rewrite_service_asset_paths(
+            &mut deserialized_code,
+            previous_service_id,
+            service_id,
+        ),
+        false => vec![],
+    };
+
     docker_compose_file
         .services
         .insert(service_id.to_string(), deserialized_code);
@@ -111,7 +189,7 @@ pub fn update_service(
         .into_iter()
         .map(|(current_service_id, mut current_service)| {
             current_service.depends_on = current_service.depends_on.map(|mut depends_on| {
-                let dependes_on_content = depends_on.remove(previous_service_id);
+                let dependes_on_content = depends_on.remove(previous_service_id.as_str());
                 if let Some(dependes_on_content) = dependes_on_content {
                     depends_on.insert(service_id.to_string(), dependes_on_content);
                 }
@@ -121,93 +199,733 @@ pub fn update_service(
         })
         .collect();
 
-    docker::write_docker_compose_file(scene_name, &docker_compose_file)
+    let assets_dirpath = assets::get_service_assets_dirpath(scene_name, previous_service_id);
+    let next_assets_dirpath = assets::get_service_assets_dirpath(scene_name, service_id);
+    let has_moved_assets = is_renamed && matches!(assets_dirpath.try_exists(), Ok(true));
+    if has_moved_assets {
+        if let Ok(true) = next_assets_dirpath.try_exists() {
+            return Err(format!(
+                "Assets folder {} already exists",
+                next_assets_dirpath.to_str().unwrap()
+            ));
+        }
+
+        fs::rename(&assets_dirpath, &next_assets_dirpath).map_err(|err| {
+            format!(
+                "Cannot move local assets from {} to {}: {err}",
+                assets_dirpath.to_str().unwrap(),
+                next_assets_dirpath.to_str().unwrap()
+            )
+        })?;
+    }
+
+    docker::write_docker_compose_file(scene_name, &mut docker_compose_file).inspect_err(|_| {
+        if has_moved_assets {
+            let _ = fs::rename(&next_assets_dirpath, &assets_dirpath);
+        }
+    })?;
+
+    Ok(rewrites)
 }
 
 #[tauri::command(async)]
-pub fn delete_service(scene_name: &str, service_id: &str) -> Result<(), String> {
+pub fn delete_service(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    service_id: ServiceId,
+    compose_file_hash: Option<String>,
+) -> Result<(), String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    docker::ensure_docker_compose_file_is_unchanged(&scene_name, compose_file_hash.as_deref())?;
+    snapshots::take_scene_snapshot(
+        &scene_name,
+        &format!("Before deleting service {service_id}"),
+        false,
+    )?;
+    // The assets folder is moved into the history entry, which deletes it
+    history::record_scene_removal(
+        &scene_name,
+        &format!("Delete service {service_id}"),
+        &[history::compose_file_path(&scene_name)],
+        &[history::service_assets_path(&scene_name, &service_id)],
+        || remove_service(&scene_name, &service_id),
+    )
+}
+
+fn remove_service(scene_name: &SceneName, service_id: &ServiceId) -> Result<(), String> {
     let mut docker_compose_file = docker::get_docker_compose_file(scene_name)?;
-    docker_compose_file.services.remove_entry(service_id);
-    docker::write_docker_compose_file(scene_name, &docker_compose_file)?;
-
-    let assets_dirpath = get_config_dirpath()
-        .join("scenes")
-        .join(scene_name)
-        .join(service_id);
-    fs::remove_dir_all(&assets_dirpath).map_err(|err| {
-        format!(
-            "Cannot delete local assets at {} for service {service_id} in scene {scene_name}: {err}",
-            assets_dirpath.to_str().unwrap(),
-        )
+    docker_compose_file
+        .services
+        .remove_entry(service_id.as_str());
+    docker::write_docker_compose_file(scene_name, &mut docker_compose_file)
+}
+
+/// Adds a copy of a service to a scene under a new id, along with its assets.
+/// Dependencies are only kept on services the target scene knows about.
+fn copy_service(
+    scene_name: &str,
+    service_id: &str,
+    target_scene_name: &str,
+    target_service_id: &str,
+    with_dependencies: bool,
+) -> Result<(), String> {
+    let mut service = docker::get_docker_compose_file(scene_name)?
+        .services
+        .remove(service_id)
+        .ok_or(format!(
+            "Cannot find service {service_id} in scene {scene_name}"
+        ))?;
+
+    let mut target_docker_compose_file = docker::get_docker_compose_file(target_scene_name)?;
+    if target_docker_compose_file
+        .services
+        .contains_key(target_service_id)
+    {
+        return Err(format!(
+            "Service with Id {target_service_id} already exists"
+        ));
+    }
+
+    service.depends_on = match with_dependencies {
+        true => {
+            let target_service_ids = scenes::read_scene_services(target_scene_name)?
+                .into_iter()
+                .map(|service| service.id)
+                .collect::<Vec<_>>();
+            service.depends_on.map(|depends_on| {
+                depends_on
+                    .into_iter()
+                    .filter(|(depends_on_id, _)| {
+                        depends_on_id != target_service_id
+                            && target_service_ids.contains(depends_on_id)
+                    })
+                    .collect()
+            })
+        }
+        false => None,
+    };
+    assets::rewrite_service_asset_paths(&mut service, service_id, target_service_id);
+
+    assets::copy_service_assets(scene_name, service_id, target_scene_name, target_service_id)?;
+
+    target_docker_compose_file
+        .services
+        .insert(target_service_id.to_string(), service);
+    docker::write_docker_compose_file(target_scene_name, &mut target_docker_compose_file)
+        .inspect_err(|_| {
+            let _ = fs::remove_dir_all(assets::get_service_assets_dirpath(
+                target_scene_name,
+                target_service_id,
+            ));
+        })
+}
+
+#[tauri::command(async)]
+pub fn duplicate_service(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    service_id: ServiceId,
+    new_service_id: ServiceId,
+    with_dependencies: Option<bool>,
+    compose_file_hash: Option<String>,
+) -> Result<(), String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    docker::ensure_docker_compose_file_is_unchanged(&scene_name, compose_file_hash.as_deref())?;
+    history::record_scene_edit(
+        &scene_name,
+        &format!("Duplicate service {service_id} as {new_service_id}"),
+        &[
+            history::compose_file_path(&scene_name),
+            history::service_assets_path(&scene_name, &new_service_id),
+        ],
+        || {
+            copy_service(
+                &scene_name,
+                &service_id,
+                &scene_name,
+                &new_service_id,
+                with_dependencies.unwrap_or(true),
+            )
+        },
+    )
+}
+
+#[tauri::command(async)]
+pub fn copy_service_to_scene(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    target_scene_name: SceneName,
+    service_id: ServiceId,
+    new_service_id: ServiceId,
+    with_dependencies: Option<bool>,
+    target_compose_file_hash: Option<String>,
+) -> Result<(), String> {
+    let _lock = state
+        .scene_write_locks
+        .lock(&[&scene_name, &target_scene_name]);
+    docker::ensure_docker_compose_file_is_unchanged(
+        &target_scene_name,
+        target_compose_file_hash.as_deref(),
+    )?;
+    history::record_scene_edit(
+        &target_scene_name,
+        &format!("Copy service {service_id} from scene {scene_name} as {new_service_id}"),
+        &[
+            history::compose_file_path(&target_scene_name),
+            history::service_assets_path(&target_scene_name, &new_service_id),
+        ],
+        || {
+            copy_service(
+                &scene_name,
+                &service_id,
+                &target_scene_name,
+                &new_service_id,
+                with_dependencies.unwrap_or(false),
+            )
+        },
+    )
+}
+
+#[derive(Serialize)]
+pub struct RemovedDependency {
+    #[serde(rename = "serviceId")]
+    pub service_id: String,
+    #[serde(rename = "dependsOn")]
+    pub depends_on: String,
+}
+
+#[derive(Serialize)]
+pub struct MoveServiceReport {
+    /// Dependencies that could not be kept because the two services are not
+    /// visible from each other's scene anymore
+    #[serde(rename = "removedDependencies")]
+    pub removed_dependencies: Vec<RemovedDependency>,
+}
+
+/// Whether `included_scene_name` is included by the scene, directly or
+/// through other included scenes
+fn is_scene_included(scene_name: &str, included_scene_name: &str) -> Result<bool, String> {
+    let mut visited_scene_names = vec![scene_name.to_string()];
+    let mut scene_names = vec![scene_name.to_string()];
+    while let Some(scene_name) = scene_names.pop() {
+        for included_scene in scenes::read_included_scenes(&scene_name)? {
+            if included_scene.name == included_scene_name {
+                return Ok(true);
+            }
+            if !visited_scene_names.contains(&included_scene.name) {
+                visited_scene_names.push(included_scene.name.clone());
+                scene_names.push(included_scene.name);
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+/// Moves a service and its assets to another scene. Dependencies between
+/// the two scenes are kept when the depending service can still see the
+/// other one through the scene includes, and dropped otherwise. Both compose
+/// files are restored if any step fails.
+#[tauri::command(async)]
+pub fn move_service(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    target_scene_name: SceneName,
+    service_id: ServiceId,
+    compose_file_hash: Option<String>,
+    target_compose_file_hash: Option<String>,
+) -> Result<MoveServiceReport, String> {
+    let _lock = state
+        .scene_write_locks
+        .lock(&[&scene_name, &target_scene_name]);
+    docker::ensure_docker_compose_file_is_unchanged(&scene_name, compose_file_hash.as_deref())?;
+    docker::ensure_docker_compose_file_is_unchanged(
+        &target_scene_name,
+        target_compose_file_hash.as_deref(),
+    )?;
+    history::record_scenes_edit(
+        &format!("Move service {service_id} from scene {scene_name} to scene {target_scene_name}"),
+        &[
+            (
+                &scene_name,
+                vec![
+                    history::compose_file_path(&scene_name),
+                    history::service_assets_path(&scene_name, &service_id),
+                ],
+            ),
+            (
+                &target_scene_name,
+                vec![
+                    history::compose_file_path(&target_scene_name),
+                    history::service_assets_path(&target_scene_name, &service_id),
+                ],
+            ),
+        ],
+        || move_service_to_scene(&scene_name, &target_scene_name, &service_id),
+    )
+}
+
+fn move_service_to_scene(
+    scene_name: &SceneName,
+    target_scene_name: &SceneName,
+    service_id: &ServiceId,
+) -> Result<MoveServiceReport, String> {
+    if scene_name == target_scene_name {
+        return Err(format!(
+            "Service {service_id} already belongs to scene {scene_name}"
+        ));
+    }
+
+    let mut docker_compose_file = docker::get_docker_compose_file(scene_name)?;
+    let mut target_docker_compose_file = docker::get_docker_compose_file(target_scene_name)?;
+    let mut previous_target_docker_compose_file =
+        docker::get_docker_compose_file(target_scene_name)?;
+
+    let mut service = docker_compose_file
+        .services
+        .remove(service_id.as_str())
+        .ok_or(format!(
+            "Cannot find service {service_id} in scene {scene_name}"
+        ))?;
+    if target_docker_compose_file
+        .services
+        .contains_key(service_id.as_str())
+    {
+        return Err(format!(
+            "Service with Id {service_id} already exists in scene {target_scene_name}"
+        ));
+    }
+
+    let mut removed_dependencies = vec![];
+
+    let target_service_ids = scenes::read_scene_services(target_scene_name)?
+        .into_iter()
+        .map(|service| service.id)
+        .collect::<Vec<_>>();
+    if let Some(depends_on) = service.depends_on.as_mut() {
+        depends_on.retain(|depends_on_id, _| {
+            let is_visible = target_service_ids.contains(depends_on_id);
+            if !is_visible {
+                removed_dependencies.push(RemovedDependency {
+                    service_id: service_id.to_string(),
+                    depends_on: depends_on_id.to_string(),
+                });
+            }
+            is_visible
+        });
+    }
+
+    // Services left behind can only keep depending on the moved service
+    // when the target scene is included by their own scene
+    if !is_scene_included(scene_name, target_scene_name)? {
+        for (current_service_id, current_service) in docker_compose_file.services.iter_mut() {
+            let depends_on = current_service
+                .depends_on
+                .as_mut()
+                .and_then(|depends_on| depends_on.remove(service_id.as_str()));
+            if depends_on.is_some() {
+                removed_dependencies.push(RemovedDependency {
+                    service_id: current_service_id.to_string(),
+                    depends_on: service_id.to_string(),
+                });
+            }
+        }
+    }
+
+    target_docker_compose_file
+        .services
+        .insert(service_id.to_string(), service);
+
+    let assets_dirpath = assets::get_service_assets_dirpath(scene_name, service_id);
+    let target_assets_dirpath = assets::get_service_assets_dirpath(target_scene_name, service_id);
+    if let Ok(true) = target_assets_dirpath.try_exists() {
+        return Err(format!(
+            "Assets folder {} already exists",
+            target_assets_dirpath.to_str().unwrap()
+        ));
+    }
+    let has_assets = matches!(assets_dirpath.try_exists(), Ok(true));
+    if has_assets {
+        fs::rename(&assets_dirpath, &target_assets_dirpath).map_err(|err| {
+            format!(
+                "Cannot move local assets from {} to {}: {err}",
+                assets_dirpath.to_str().unwrap(),
+                target_assets_dirpath.to_str().unwrap()
+            )
+        })?;
+    }
+
+    let restore_assets = || {
+        if has_assets {
+            let _ = fs::rename(&target_assets_dirpath, &assets_dirpath);
+        }
+    };
+
+    docker::write_docker_compose_file(target_scene_name, &mut target_docker_compose_file)
+        .inspect_err(|_| restore_assets())?;
+    docker::write_docker_compose_file(scene_name, &mut docker_compose_file).inspect_err(|_| {
+        // The target file now holds what was just written
+        previous_target_docker_compose_file.source_hash = target_docker_compose_file.source_hash;
+        let _ = docker::write_docker_compose_file(
+            target_scene_name,
+            &mut previous_target_docker_compose_file,
+        );
+        restore_assets();
+    })?;
+
+    Ok(MoveServiceReport {
+        removed_dependencies,
     })
 }
 
 #[tauri::command(async)]
-pub async fn start_emitting_scene_status(app: AppHandle, scene_name: &str) -> Result<(), String> {
-    docker::start_emitting_scene_status(&app, scene_name).await
+pub async fn start_emitting_scene_status(
+    app: AppHandle,
+    scene_name: SceneName,
+) -> Result<(), String> {
+    docker::start_emitting_scene_status(&app, &scene_name).await
 }
 
 #[tauri::command(async)]
 pub async fn stop_emitting_scene_status(
     state: State<'_, AppState>,
-    scene_name: &str,
+    scene_name: SceneName,
 ) -> Result<(), String> {
-    docker::stop_emitting_scene_status(state, scene_name).await
+    docker::stop_emitting_scene_status(state, &scene_name).await
+}
+
+#[tauri::command(async)]
+pub async fn get_service_health_history(
+    scene_name: SceneName,
+    service_id: ServiceId,
+    limit: Option<usize>,
+) -> Result<Option<ServiceHealth>, String> {
+    docker::get_service_health_history(&scene_name, &service_id, limit).await
+}
+
+#[tauri::command(async)]
+pub async fn get_service_endpoints(
+    scene_name: SceneName,
+    service_id: ServiceId,
+) -> Result<Vec<ServiceEndpoint>, String> {
+    ports::get_service_endpoints(&scene_name, &service_id).await
 }
 
 #[tauri::command(async)]
-pub fn run_service(scene_name: &str, service_id: &str) -> Result<(), String> {
-    docker::run_docker_compose_up(scene_name, Some(service_id))
+pub async fn run_service(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    service_id: ServiceId,
+) -> Result<(), String> {
+    ports::check_scene_port_conflicts(&scene_name, Some(&service_id)).await?;
+
+    // Rendering and `compose up` block until done, away from the async workers
+    let scene_write_locks = state.scene_write_locks.clone();
+    tokio::task::spawn_blocking(move || {
+        templates::render_scene_templates(&scene_write_locks, &scene_name, Some(&service_id))?;
+        docker::run_docker_compose_up(&scene_name, Some(&service_id))
+    })
+    .await
+    .map_err(|err| format!("Cannot start service: {err}"))?
 }
 
 #[tauri::command(async)]
-pub fn stop_service(scene_name: &str, service_id: &str) -> Result<(), String> {
-    docker::run_docker_compose_down(scene_name, Some(service_id))
+pub fn stop_service(scene_name: SceneName, service_id: ServiceId) -> Result<(), String> {
+    docker::run_docker_compose_down(&scene_name, Some(&service_id), false)
 }
 
 #[tauri::command(async)]
 pub async fn start_emitting_service_logs(
     app: AppHandle,
-    scene_name: &str,
-    service_id: &str,
+    scene_name: SceneName,
+    service_id: ServiceId,
+    min_level: Option<LogLevel>,
 ) -> Result<(), String> {
-    docker::start_emitting_service_logs(&app, scene_name, service_id).await
+    logs::start_emitting_service_logs(&app, &scene_name, &service_id, min_level).await
 }
 
 #[tauri::command(async)]
 pub async fn stop_emitting_service_logs(
     state: State<'_, AppState>,
-    scene_name: &str,
-    service_id: &str,
+    scene_name: SceneName,
+    service_id: ServiceId,
+) -> Result<(), String> {
+    logs::stop_emitting_service_logs(state, &scene_name, &service_id).await
+}
+
+#[tauri::command(async)]
+pub async fn start_watching_service_assets(
+    app: AppHandle,
+    scene_name: SceneName,
+    service_id: ServiceId,
+    action: Option<AssetWatchAction>,
+    debounce: Option<u64>,
+) -> Result<(), String> {
+    watchers::start_watching_service_assets(
+        &app,
+        &scene_name,
+        &service_id,
+        action.unwrap_or(AssetWatchAction::Restart),
+        debounce,
+    )
+    .await
+}
+
+#[tauri::command(async)]
+pub async fn stop_watching_service_assets(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    service_id: ServiceId,
+) -> Result<(), String> {
+    watchers::stop_watching_service_assets(state, &scene_name, &service_id).await
+}
+
+#[tauri::command(async)]
+pub async fn export_service_logs(
+    scene_name: SceneName,
+    service_id: ServiceId,
+    filepath: &str,
+    format: logs::LogExportFormat,
+    filters: Option<logs::LogExportFilters>,
+) -> Result<usize, String> {
+    logs::export_service_logs(
+        &scene_name,
+        &service_id,
+        filepath,
+        format,
+        filters.unwrap_or_default(),
+    )
+    .await
+}
+
+#[tauri::command(async)]
+pub async fn start_persisting_service_logs(
+    app: AppHandle,
+    scene_name: SceneName,
+    service_id: ServiceId,
+    max_size: Option<u64>,
+) -> Result<(), String> {
+    log_storage::start_persisting_service_logs(&app, &scene_name, &service_id, max_size).await
+}
+
+#[tauri::command(async)]
+pub async fn stop_persisting_service_logs(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    service_id: ServiceId,
+) -> Result<(), String> {
+    log_storage::stop_persisting_service_logs(state, &scene_name, &service_id).await
+}
+
+#[tauri::command(async)]
+pub fn get_persisted_service_log_runs(
+    scene_name: SceneName,
+    service_id: ServiceId,
+) -> Result<Vec<PersistedLogRun>, String> {
+    log_storage::get_persisted_service_log_runs(&scene_name, &service_id)
+}
+
+#[tauri::command(async)]
+pub fn get_persisted_service_logs(
+    scene_name: SceneName,
+    service_id: ServiceId,
+    session: Option<&str>,
+    run: Option<&str>,
+) -> Result<Vec<PersistedLogLine>, String> {
+    log_storage::get_persisted_service_logs(&scene_name, &service_id, session, run)
+}
+
+#[tauri::command(async)]
+pub async fn pull_service_image(
+    app: AppHandle,
+    scene_name: SceneName,
+    service_id: ServiceId,
+) -> Result<(), String> {
+    images::pull_service_image(&app, &scene_name, &service_id).await
+}
+
+#[tauri::command(async)]
+pub async fn build_service(
+    app: AppHandle,
+    scene_name: SceneName,
+    service_id: ServiceId,
+) -> Result<(), String> {
+    images::build_service(&app, &scene_name, &service_id).await
+}
+
+#[tauri::command(async)]
+pub async fn cancel_service_image_operation(
+    app: AppHandle,
+    scene_name: SceneName,
+    service_id: ServiceId,
 ) -> Result<(), String> {
-    docker::stop_emitting_service_logs(state, scene_name, service_id).await
+    images::cancel_service_image_operation(&app, &scene_name, &service_id).await
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-#[serde(untagged)]
-pub enum ServiceAssets {
-    Leaf,
-    Node(BTreeMap<String, ServiceAssets>),
+#[derive(Serialize, Debug, Clone)]
+pub struct ServiceAsset {
+    /// Missing for files
+    pub children: Option<BTreeMap<String, ServiceAsset>>,
+    pub usages: Vec<AssetUsage>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ServiceAssets {
+    pub entries: BTreeMap<String, ServiceAsset>,
+    /// Usages of the whole assets folder
+    pub usages: Vec<AssetUsage>,
+    /// Usages of the service pointing at assets that do not exist
+    #[serde(rename = "missingMounts")]
+    pub missing_mounts: Vec<AssetUsage>,
 }
 
 #[tauri::command(async)]
 pub fn get_service_assets(
-    scene_name: &str,
-    service_id: &str,
-) -> Result<BTreeMap<String, ServiceAssets>, String> {
-    let service_assets_dirpath = get_config_dirpath()
-        .join("scenes")
-        .join(scene_name)
-        .join(service_id);
+    scene_name: SceneName,
+    service_id: ServiceId,
+) -> Result<ServiceAssets, String> {
+    let service_assets_dirpath = assets::get_service_assets_dirpath(&scene_name, &service_id);
+
+    let docker_compose_file = docker::get_docker_compose_file(&scene_name)?;
+    let (usages, entry_usages): (Vec<_>, Vec<_>) =
+        assets::get_service_asset_usages(&docker_compose_file, &service_id)
+            .into_iter()
+            .partition(|usage| usage.path.is_empty());
+    let missing_mounts = entry_usages
+        .iter()
+        .filter(|usage| {
+            !matches!(
+                service_assets_dirpath.join(&usage.path).try_exists(),
+                Ok(true)
+            )
+        })
+        .cloned()
+        .collect();
+
+    let entries = get_service_assets_recursive(
+        service_assets_dirpath,
+        Path::new(""),
+        &entry_usages,
+        &scene_name,
+        &service_id,
+    )?;
+
+    Ok(ServiceAssets {
+        entries,
+        usages,
+        missing_mounts,
+    })
+}
 
-    get_service_assets_recursive(service_assets_dirpath, scene_name, service_id)
+#[tauri::command(async)]
+pub fn delete_asset(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    service_id: ServiceId,
+    asset_path: AssetPath,
+) -> Result<(), String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    history::record_scene_edit(
+        &scene_name,
+        &format!("Delete asset {asset_path} of service {service_id}"),
+        &[history::asset_path(&scene_name, &service_id, &asset_path)?],
+        || assets::delete_asset(&scene_name, &service_id, &asset_path),
+    )
+}
+
+#[tauri::command(async)]
+pub fn rename_asset(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    service_id: ServiceId,
+    asset_path: AssetPath,
+    name: &str,
+) -> Result<(), String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    let target = AssetPath::new(&asset_path.with_file_name(name).to_string_lossy())?;
+    history::record_scene_edit(
+        &scene_name,
+        &format!("Rename asset {asset_path} of service {service_id} to {name}"),
+        &[
+            history::asset_path(&scene_name, &service_id, &asset_path)?,
+            history::asset_path(&scene_name, &service_id, &target)?,
+        ],
+        || assets::rename_asset(&scene_name, &service_id, &asset_path, name),
+    )
+}
+
+#[tauri::command(async)]
+pub fn move_asset(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    service_id: ServiceId,
+    asset_path: AssetPath,
+    target: AssetPath,
+) -> Result<(), String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    history::record_scene_edit(
+        &scene_name,
+        &format!("Move asset {asset_path} of service {service_id} to {target}"),
+        &[
+            history::asset_path(&scene_name, &service_id, &asset_path)?,
+            history::asset_path(&scene_name, &service_id, &target)?,
+        ],
+        || assets::move_asset(&scene_name, &service_id, &asset_path, &target),
+    )
+}
+
+#[tauri::command(async)]
+pub fn read_asset_file(
+    scene_name: SceneName,
+    service_id: ServiceId,
+    asset_path: AssetPath,
+) -> Result<AssetFile, String> {
+    assets::read_asset_file(&scene_name, &service_id, &asset_path)
+}
+
+#[tauri::command(async)]
+pub fn write_asset_file(
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    service_id: ServiceId,
+    asset_path: AssetPath,
+    content: &str,
+    encoding: Option<AssetEncoding>,
+    bom: Option<bool>,
+) -> Result<(), String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
+    history::record_scene_edit(
+        &scene_name,
+        &format!("Edit asset {asset_path} of service {service_id}"),
+        &[history::asset_path(&scene_name, &service_id, &asset_path)?],
+        || {
+            assets::write_asset_file(
+                &scene_name,
+                &service_id,
+                &asset_path,
+                content,
+                encoding.unwrap_or_default(),
+                bom.unwrap_or(false),
+            )
+        },
+    )
+}
+
+#[tauri::command(async)]
+pub fn preview_service_templates(
+    scene_name: SceneName,
+    service_id: ServiceId,
+) -> Result<Vec<RenderedTemplate>, String> {
+    templates::preview_service_templates(&scene_name, &service_id)
 }
 
 fn get_service_assets_recursive(
     dirpath: PathBuf,
+    relative_dirpath: &Path,
+    usages: &[AssetUsage],
     scene_name: &str,
     service_id: &str,
-) -> Result<BTreeMap<String, ServiceAssets>, String> {
+) -> Result<BTreeMap<String, ServiceAsset>, String> {
     let mut service_assets = BTreeMap::new();
 
     match dirpath.try_exists() {
@@ -220,19 +938,30 @@ fn get_service_assets_recursive(
 
                     let is_dir = entry.path().is_dir();
                     let entry_name = entry.file_name().to_string_lossy().to_string();
-                    if is_dir {
-                        let next_service_assets = get_service_assets_recursive(entry.path(), scene_name, service_id)?;
-
-                        service_assets.insert(
-                            entry_name,
-                            ServiceAssets::Node(next_service_assets)
-                        );
-                    } else {
-                        service_assets.insert(
-                            entry_name,
-                            ServiceAssets::Leaf,
-                        );
-                    }
+                    let relative_path = relative_dirpath.join(&entry_name);
+                    let entry_usages = usages
+                        .iter()
+                        .filter(|usage| Path::new(&usage.path) == relative_path)
+                        .cloned()
+                        .collect();
+
+                    let children = match is_dir {
+                        true => Some(get_service_assets_recursive(
+                            entry.path(),
+                            &relative_path,
+                            usages,
+                            scene_name,
+                            service_id,
+                        )?),
+                        false => None,
+                    };
+                    service_assets.insert(
+                        entry_name,
+                        ServiceAsset {
+                            children,
+                            usages: entry_usages,
+                        },
+                    );
                 }
             }
         }
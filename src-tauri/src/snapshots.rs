@@ -0,0 +1,428 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    docker,
+    paths::SceneName,
+    utils::{get_config_dirpath, write_file_atomically},
+    watchers,
+};
+
+/// Oldest automatic snapshots are deleted past this number, per scene. The
+/// ones taken on demand are kept until they are deleted.
+const MAX_AUTOMATIC_SNAPSHOTS: usize = 20;
+
+const SNAPSHOT_FILENAME: &str = "snapshot.json";
+const SNAPSHOT_FILES_DIRNAME: &str = "files";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SceneSnapshot {
+    pub id: String,
+    #[serde(rename = "sceneName")]
+    pub scene_name: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    /// Operation the snapshot was taken before, or the reason given by the
+    /// user for snapshots taken on demand
+    pub reason: String,
+    #[serde(rename = "onDemand", default)]
+    pub on_demand: bool,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffLineKind {
+    Unchanged,
+    Added,
+    Removed,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    pub content: String,
+}
+
+/// Changes made to a scene since a snapshot was taken
+#[derive(Serialize, Debug)]
+pub struct SnapshotDiff {
+    #[serde(rename = "composeFile")]
+    pub compose_file: Vec<DiffLine>,
+    /// Paths are relative to the scene folder
+    #[serde(rename = "addedFiles")]
+    pub added_files: Vec<String>,
+    #[serde(rename = "removedFiles")]
+    pub removed_files: Vec<String>,
+    #[serde(rename = "modifiedFiles")]
+    pub modified_files: Vec<String>,
+}
+
+fn get_snapshots_dirpath(scene_name: &str) -> PathBuf {
+    get_config_dirpath().join("snapshots").join(scene_name)
+}
+
+/// Snapshot ids are timestamps, anything else could lead out of the
+/// snapshots folder
+fn get_snapshot_dirpath(scene_name: &str, snapshot_id: &str) -> Result<PathBuf, String> {
+    if snapshot_id.is_empty() || !snapshot_id.chars().all(|char| char.is_ascii_digit()) {
+        return Err(format!("Invalid snapshot id {snapshot_id:?}"));
+    }
+
+    let snapshot_dirpath = get_snapshots_dirpath(scene_name).join(snapshot_id);
+    if !snapshot_dirpath.join(SNAPSHOT_FILENAME).is_file() {
+        return Err(format!(
+            "Cannot find snapshot {snapshot_id} of scene {scene_name}"
+        ));
+    }
+
+    Ok(snapshot_dirpath)
+}
+
+fn copy_dir_content(dirpath: &Path, target_dirpath: &Path) -> Result<(), String> {
+    fs_extra::dir::create_all(target_dirpath, false)
+        .map_err(|err| format!("Cannot create folder {}: {err}", target_dirpath.display()))?;
+
+    let options = fs_extra::dir::CopyOptions {
+        content_only: true,
+        ..Default::default()
+    };
+    fs_extra::dir::copy(dirpath, target_dirpath, &options)
+        .map(|_| ())
+        .map_err(|err| {
+            format!(
+                "Could not copy files from {} to {}: {err}",
+                dirpath.display(),
+                target_dirpath.display()
+            )
+        })
+}
+
+pub fn list_snapshots(scene_name: &str) -> Result<Vec<SceneSnapshot>, String> {
+    let snapshots_dirpath = get_snapshots_dirpath(scene_name);
+    if !snapshots_dirpath.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let dir = fs::read_dir(&snapshots_dirpath)
+        .map_err(|err| format!("Cannot read snapshots of scene {scene_name}: {err}"))?;
+
+    let mut snapshots = vec![];
+    for entry in dir.flatten() {
+        let Ok(snapshot) = fs::read_to_string(entry.path().join(SNAPSHOT_FILENAME)) else {
+            continue;
+        };
+        if let Ok(snapshot) = serde_json::from_str::<SceneSnapshot>(&snapshot) {
+            snapshots.push(snapshot);
+        }
+    }
+
+    snapshots.sort_by_key(|snapshot| Reverse(snapshot.created_at));
+    Ok(snapshots)
+}
+
+/// Creates the folder of a new snapshot. Ids taken in the same millisecond
+/// get a numeric suffix, so that they stay valid snapshot ids.
+fn create_snapshot_dirpath(
+    scene_name: &str,
+    created_at: &DateTime<Utc>,
+) -> Result<(String, PathBuf), String> {
+    let snapshots_dirpath = get_snapshots_dirpath(scene_name);
+    fs_extra::dir::create_all(&snapshots_dirpath, false)
+        .map_err(|err| format!("Cannot create snapshots folder of scene {scene_name}: {err}"))?;
+
+    let timestamp = created_at.format("%Y%m%d%H%M%S%3f").to_string();
+    for suffix in 0.. {
+        let id = match suffix {
+            0 => timestamp.clone(),
+            suffix => format!("{timestamp}{suffix}"),
+        };
+        let snapshot_dirpath = snapshots_dirpath.join(&id);
+        match fs::create_dir(&snapshot_dirpath) {
+            Ok(_) => return Ok((id, snapshot_dirpath)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => {
+                return Err(format!(
+                    "Cannot create snapshot {id} of scene {scene_name}: {err}"
+                ))
+            }
+        }
+    }
+    unreachable!()
+}
+
+/// Copies the compose file and the assets of a scene aside
+pub fn take_scene_snapshot(
+    scene_name: &SceneName,
+    reason: &str,
+    on_demand: bool,
+) -> Result<SceneSnapshot, String> {
+    let created_at = Utc::now();
+    let (id, snapshot_dirpath) = create_snapshot_dirpath(scene_name, &created_at)?;
+    let snapshot = SceneSnapshot {
+        id,
+        scene_name: scene_name.to_string(),
+        created_at,
+        reason: reason.to_string(),
+        on_demand,
+    };
+
+    copy_dir_content(
+        &scene_name.dirpath(),
+        &snapshot_dirpath.join(SNAPSHOT_FILES_DIRNAME),
+    )
+    .and_then(|_| {
+        // Written last, folders without it are incomplete snapshots
+        write_file_atomically(
+            &snapshot_dirpath.join(SNAPSHOT_FILENAME),
+            serde_json::to_string(&snapshot).unwrap().as_bytes(),
+        )
+        .map_err(|err| format!("Cannot write snapshot of scene {scene_name}: {err}"))
+    })
+    .inspect_err(|_| {
+        let _ = fs::remove_dir_all(&snapshot_dirpath);
+    })?;
+
+    let automatic_snapshots = list_snapshots(scene_name)?
+        .into_iter()
+        .filter(|snapshot| !snapshot.on_demand);
+    for snapshot in automatic_snapshots.skip(MAX_AUTOMATIC_SNAPSHOTS) {
+        let _ = fs::remove_dir_all(get_snapshots_dirpath(scene_name).join(&snapshot.id));
+    }
+
+    Ok(snapshot)
+}
+
+pub fn delete_snapshot(scene_name: &str, snapshot_id: &str) -> Result<(), String> {
+    let snapshot_dirpath = get_snapshot_dirpath(scene_name, snapshot_id)?;
+    fs::remove_dir_all(snapshot_dirpath)
+        .map_err(|err| format!("Cannot delete snapshot {snapshot_id}: {err}"))
+}
+
+/// Diffs two texts line by line through their longest common subsequence
+fn diff_lines(previous: &str, current: &str) -> Vec<DiffLine> {
+    let previous_lines = previous.lines().collect::<Vec<_>>();
+    let current_lines = current.lines().collect::<Vec<_>>();
+
+    let mut lengths = vec![vec![0; current_lines.len() + 1]; previous_lines.len() + 1];
+    for i in (0..previous_lines.len()).rev() {
+        for j in (0..current_lines.len()).rev() {
+            lengths[i][j] = match previous_lines[i] == current_lines[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+
+    let mut diff = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < previous_lines.len() || j < current_lines.len() {
+        let (kind, content) = if i < previous_lines.len()
+            && j < current_lines.len()
+            && previous_lines[i] == current_lines[j]
+        {
+            i += 1;
+            j += 1;
+            (DiffLineKind::Unchanged, current_lines[j - 1])
+        } else if i < previous_lines.len()
+            && (j == current_lines.len() || lengths[i + 1][j] >= lengths[i][j + 1])
+        {
+            // Removed lines come before the lines added in their place
+            i += 1;
+            (DiffLineKind::Removed, previous_lines[i - 1])
+        } else {
+            j += 1;
+            (DiffLineKind::Added, current_lines[j - 1])
+        };
+
+        diff.push(DiffLine {
+            kind,
+            content: content.to_string(),
+        });
+    }
+
+    diff
+}
+
+pub fn diff_snapshot(scene_name: &SceneName, snapshot_id: &str) -> Result<SnapshotDiff, String> {
+    let snapshot_files_dirpath =
+        get_snapshot_dirpath(scene_name, snapshot_id)?.join(SNAPSHOT_FILES_DIRNAME);
+    let scene_dirpath = scene_name.dirpath();
+
+    let read_compose_file =
+        |dirpath: &Path| fs::read_to_string(dirpath.join("docker-compose.yml")).unwrap_or_default();
+    let compose_file = diff_lines(
+        &read_compose_file(&snapshot_files_dirpath),
+        &read_compose_file(&scene_dirpath),
+    );
+
+    let snapshot_paths = watchers::snapshot_files(&snapshot_files_dirpath)
+        .into_keys()
+        .collect::<BTreeSet<_>>();
+    let current_paths = watchers::snapshot_files(&scene_dirpath)
+        .into_keys()
+        .collect::<BTreeSet<_>>();
+
+    let to_strings = |paths: Vec<&PathBuf>| {
+        paths
+            .into_iter()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    Ok(SnapshotDiff {
+        compose_file,
+        added_files: to_strings(current_paths.difference(&snapshot_paths).collect()),
+        removed_files: to_strings(snapshot_paths.difference(&current_paths).collect()),
+        modified_files: to_strings(
+            snapshot_paths
+                .intersection(&current_paths)
+                .filter(|path| {
+                    fs::read(snapshot_files_dirpath.join(path)).ok()
+                        != fs::read(scene_dirpath.join(path)).ok()
+                })
+                .collect(),
+        ),
+    })
+}
+
+/// Replaces the scene folder with the content of a snapshot. The scene is
+/// recreated when it was deleted since.
+pub fn restore_snapshot(scene_name: &SceneName, snapshot_id: &str) -> Result<(), String> {
+    let snapshot_dirpath = get_snapshot_dirpath(scene_name, snapshot_id)?;
+    let scene_dirpath = scene_name.dirpath();
+
+    // The copy is made next to the snapshots first so that a failed copy
+    // leaves the scene untouched
+    let restored_dirpath = get_snapshots_dirpath(scene_name).join(format!("{snapshot_id}-restore"));
+    let _ = fs::remove_dir_all(&restored_dirpath);
+    copy_dir_content(
+        &snapshot_dirpath.join(SNAPSHOT_FILES_DIRNAME),
+        &restored_dirpath,
+    )
+    .inspect_err(|_| {
+        let _ = fs::remove_dir_all(&restored_dirpath);
+    })?;
+
+    // The current scene is only deleted once the restored copy took its place,
+    // and is moved back if it cannot
+    let previous_hash = docker::get_docker_compose_file_hash(scene_name);
+    let replaced_dirpath =
+        get_snapshots_dirpath(scene_name).join(format!("{snapshot_id}-replaced"));
+    let _ = fs::remove_dir_all(&replaced_dirpath);
+    let scene_exists = scene_dirpath.exists();
+    if scene_exists {
+        fs::rename(&scene_dirpath, &replaced_dirpath)
+            .inspect_err(|_| {
+                let _ = fs::remove_dir_all(&restored_dirpath);
+            })
+            .map_err(|err| {
+                format!(
+                    "Cannot move scene {scene_name} aside to restore snapshot {snapshot_id}: {err}"
+                )
+            })?;
+    }
+    if let Err(err) = fs::rename(&restored_dirpath, &scene_dirpath) {
+        if scene_exists {
+            let _ = fs::rename(&replaced_dirpath, &scene_dirpath);
+        }
+        let _ = fs::remove_dir_all(&restored_dirpath);
+        return Err(format!(
+            "Cannot restore snapshot {snapshot_id} of scene {scene_name}: {err}"
+        ));
+    }
+    let _ = fs::remove_dir_all(&replaced_dirpath);
+
+    if let Some(hash) = docker::get_docker_compose_file_hash(scene_name) {
+        docker::record_docker_compose_file_write(scene_name, previous_hash, hash);
+    }
+    Ok(())
+}
+
+/// Moves a scene folder to the trash instead of deleting it
+pub fn trash_scene(scene_name: &SceneName) -> Result<(), String> {
+    let trash_dirpath = get_config_dirpath().join("trash");
+    fs_extra::dir::create_all(&trash_dirpath, false)
+        .map_err(|err| format!("Cannot create trash folder: {err}"))?;
+
+    let trashed_dirpath = trash_dirpath.join(format!(
+        "{scene_name}-{}",
+        Utc::now().format("%Y%m%d%H%M%S%3f")
+    ));
+    fs::rename(scene_name.dirpath(), trashed_dirpath)
+        .map_err(|err| format!("Could not delete scene: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_diff(previous: &str, current: &str) -> Vec<(DiffLineKind, String)> {
+        diff_lines(previous, current)
+            .into_iter()
+            .map(|line| (line.kind, line.content))
+            .collect()
+    }
+
+    fn get_lines(lines: &[(DiffLineKind, &str)]) -> Vec<(DiffLineKind, String)> {
+        lines
+            .iter()
+            .map(|(kind, content)| (*kind, content.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn diff_lines_keeps_identical_texts_unchanged() {
+        assert_eq!(
+            get_diff("a\nb", "a\nb"),
+            get_lines(&[
+                (DiffLineKind::Unchanged, "a"),
+                (DiffLineKind::Unchanged, "b")
+            ])
+        );
+        assert_eq!(get_diff("", ""), vec![]);
+    }
+
+    #[test]
+    fn diff_lines_detects_added_and_removed_lines() {
+        assert_eq!(
+            get_diff("a\nb\nc", "a\nc\nd"),
+            get_lines(&[
+                (DiffLineKind::Unchanged, "a"),
+                (DiffLineKind::Removed, "b"),
+                (DiffLineKind::Unchanged, "c"),
+                (DiffLineKind::Added, "d"),
+            ])
+        );
+        assert_eq!(
+            get_diff("", "a\nb"),
+            get_lines(&[(DiffLineKind::Added, "a"), (DiffLineKind::Added, "b")])
+        );
+        assert_eq!(
+            get_diff("a\nb", ""),
+            get_lines(&[(DiffLineKind::Removed, "a"), (DiffLineKind::Removed, "b")])
+        );
+    }
+
+    #[test]
+    fn diff_lines_shows_modified_lines_as_removed_then_added() {
+        assert_eq!(
+            get_diff(
+                "services:\n  web:\n    image: nginx:1\n",
+                "services:\n  web:\n    image: nginx:2\n"
+            ),
+            get_lines(&[
+                (DiffLineKind::Unchanged, "services:"),
+                (DiffLineKind::Unchanged, "  web:"),
+                (DiffLineKind::Removed, "    image: nginx:1"),
+                (DiffLineKind::Added, "    image: nginx:2"),
+            ])
+        );
+    }
+}
@@ -1,5 +1,9 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{self, Arc, Condvar},
+};
 
+use chrono::Utc;
 use tokio::{sync::Mutex, task::JoinHandle};
 
 #[derive(Eq, Hash, PartialEq)]
@@ -12,12 +16,83 @@ pub struct ServiceKey {
 pub struct AppState {
     pub service_log_handles: Arc<Mutex<HashMap<ServiceKey, JoinHandle<()>>>>,
     pub service_status_handles: Arc<Mutex<HashMap<String, Vec<JoinHandle<()>>>>>,
+    pub scene_log_handles: Arc<Mutex<HashMap<String, JoinHandle<()>>>>,
+    pub scene_stats_handles: Arc<Mutex<HashMap<String, Vec<JoinHandle<()>>>>>,
+    pub service_image_handles: Arc<Mutex<HashMap<ServiceKey, JoinHandle<()>>>>,
+    pub service_log_capture_handles: Arc<Mutex<HashMap<ServiceKey, JoinHandle<()>>>>,
+    pub service_asset_watch_handles: Arc<Mutex<HashMap<ServiceKey, JoinHandle<()>>>>,
+    pub scene_watch_handles: Arc<Mutex<HashMap<String, JoinHandle<()>>>>,
+    pub scene_write_locks: Arc<SceneWriteLocks>,
+    /// Identifies the current app run in persisted logs
+    pub session_id: String,
 }
 
 impl AppState {
     pub fn new() -> Self {
         Self {
+            session_id: Utc::now().format("%Y%m%d%H%M%S").to_string(),
             ..Default::default()
         }
     }
 }
+
+/// Serializes the read-modify-write cycles of the compose files, per scene.
+/// The commands taking these locks are synchronous functions that Tauri runs
+/// on the async runtime, so a contended lock is waited for outside of the
+/// runtime workers.
+#[derive(Default)]
+pub struct SceneWriteLocks {
+    locked_scene_names: sync::Mutex<HashSet<String>>,
+    released: Condvar,
+}
+
+impl SceneWriteLocks {
+    /// Waits until none of the scenes is locked and locks all of them at
+    /// once, so that commands editing several scenes cannot deadlock
+    pub fn lock(&self, scene_names: &[&str]) -> SceneWriteGuard<'_> {
+        let is_locked = |locked_scene_names: &HashSet<String>| {
+            scene_names
+                .iter()
+                .any(|scene_name| locked_scene_names.contains(*scene_name))
+        };
+
+        let mut locked_scene_names = self.locked_scene_names.lock().unwrap();
+        if is_locked(&locked_scene_names) {
+            // Hands the worker's other tasks over to another thread while
+            // waiting, which is a no-op outside of the runtime workers
+            locked_scene_names = tokio::task::block_in_place(|| {
+                self.released
+                    .wait_while(locked_scene_names, |locked_scene_names| {
+                        is_locked(locked_scene_names)
+                    })
+                    .unwrap()
+            });
+        }
+
+        let scene_names = scene_names
+            .iter()
+            .map(|scene_name| scene_name.to_string())
+            .collect::<HashSet<_>>();
+        locked_scene_names.extend(scene_names.iter().cloned());
+
+        SceneWriteGuard {
+            locks: self,
+            scene_names,
+        }
+    }
+}
+
+pub struct SceneWriteGuard<'a> {
+    locks: &'a SceneWriteLocks,
+    scene_names: HashSet<String>,
+}
+
+impl Drop for SceneWriteGuard<'_> {
+    fn drop(&mut self) {
+        let mut locked_scene_names = self.locks.locked_scene_names.lock().unwrap();
+        for scene_name in &self.scene_names {
+            locked_scene_names.remove(scene_name);
+        }
+        self.locks.released.notify_all();
+    }
+}
@@ -0,0 +1,261 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use bollard::{
+    container::{MemoryStatsStats, Stats, StatsOptions},
+    Docker,
+};
+use futures::{
+    stream::{self, select_all},
+    StreamExt,
+};
+use serde::Serialize;
+use tauri::{AppHandle, Manager, State};
+use tokio::{select, spawn};
+
+use crate::{
+    docker::{self, DockerComposeContainer},
+    state::AppState,
+};
+
+const DEFAULT_STATS_INTERVAL: Duration = Duration::from_secs(2);
+/// Docker streams the stats of a container about once a second, shorter
+/// intervals would not emit them more often
+const MIN_STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Clone)]
+struct ServiceStatsEventPayload {
+    replica: u32,
+    #[serde(rename = "cpuPercentage")]
+    cpu_percentage: f64,
+    #[serde(rename = "memoryUsage")]
+    memory_usage: u64,
+    #[serde(rename = "memoryLimit")]
+    memory_limit: u64,
+    #[serde(rename = "memoryPercentage")]
+    memory_percentage: f64,
+    #[serde(rename = "networkRx")]
+    network_rx: u64,
+    #[serde(rename = "networkTx")]
+    network_tx: u64,
+    #[serde(rename = "blockRead")]
+    block_read: u64,
+    #[serde(rename = "blockWrite")]
+    block_write: u64,
+}
+
+/// Computes the same figures shown by `docker stats`
+fn get_service_stats_event_payload(replica: u32, stats: &Stats) -> ServiceStatsEventPayload {
+    let cpu_delta = stats
+        .cpu_stats
+        .cpu_usage
+        .total_usage
+        .saturating_sub(stats.precpu_stats.cpu_usage.total_usage);
+    let system_delta = stats
+        .cpu_stats
+        .system_cpu_usage
+        .unwrap_or_default()
+        .saturating_sub(stats.precpu_stats.system_cpu_usage.unwrap_or_default());
+    let online_cpus = stats.cpu_stats.online_cpus.unwrap_or_else(|| {
+        stats
+            .cpu_stats
+            .cpu_usage
+            .percpu_usage
+            .as_ref()
+            .map(|percpu_usage| percpu_usage.len() as u64)
+            .unwrap_or(1)
+    });
+    let cpu_percentage = match system_delta {
+        0 => 0.0,
+        system_delta => cpu_delta as f64 / system_delta as f64 * online_cpus as f64 * 100.0,
+    };
+
+    // The page cache is not accounted as used memory
+    let inactive_file = match stats.memory_stats.stats {
+        Some(MemoryStatsStats::V1(stats)) => stats.total_inactive_file,
+        Some(MemoryStatsStats::V2(stats)) => stats.inactive_file,
+        None => 0,
+    };
+    let memory_usage = stats
+        .memory_stats
+        .usage
+        .unwrap_or_default()
+        .saturating_sub(inactive_file);
+    let memory_limit = stats.memory_stats.limit.unwrap_or_default();
+    let memory_percentage = match memory_limit {
+        0 => 0.0,
+        memory_limit => memory_usage as f64 / memory_limit as f64 * 100.0,
+    };
+
+    let (network_rx, network_tx) = stats
+        .networks
+        .as_ref()
+        .map(|networks| {
+            networks.values().fold((0, 0), |(rx, tx), network| {
+                (rx + network.rx_bytes, tx + network.tx_bytes)
+            })
+        })
+        .unwrap_or_default();
+
+    let (block_read, block_write) = stats
+        .blkio_stats
+        .io_service_bytes_recursive
+        .as_ref()
+        .map(|entries| {
+            entries.iter().fold((0, 0), |(read, write), entry| {
+                match entry.op.to_lowercase().as_str() {
+                    "read" => (read + entry.value, write),
+                    "write" => (read, write + entry.value),
+                    _ => (read, write),
+                }
+            })
+        })
+        .unwrap_or_default();
+
+    ServiceStatsEventPayload {
+        replica,
+        cpu_percentage,
+        memory_usage,
+        memory_limit,
+        memory_percentage,
+        network_rx,
+        network_tx,
+        block_read,
+        block_write,
+    }
+}
+
+pub async fn start_emitting_scene_stats(
+    app: &AppHandle,
+    scene_name: &str,
+    interval: Option<u64>,
+) -> Result<(), String> {
+    let docker = Docker::connect_with_socket_defaults()
+        .map_err(|error| format!("Cannot connect to docker socket: {}", error))?;
+
+    let interval = interval
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_STATS_INTERVAL)
+        .max(MIN_STATS_INTERVAL);
+
+    let service_ids = docker::get_scene_service_ids(scene_name)?;
+    let mut stats_handles = Vec::with_capacity(service_ids.len());
+
+    for service_id in service_ids {
+        let thread_app = app.to_owned();
+        let thread_scene_name = scene_name.to_string();
+        let thread_docker = docker.clone();
+
+        let stats_handle = spawn(async move {
+            let service_stats_event_name = format!("{thread_scene_name}-{service_id}-stats-event");
+
+            // Subscribed before listing the containers so that no start is missed
+            let mut started_containers = docker::get_started_containers(
+                &thread_docker,
+                &thread_scene_name,
+                Some(&service_id),
+            );
+
+            // Streams end with a `None` stats, telling that the container is
+            // no longer followed
+            let get_stats_stream = |container: &DockerComposeContainer| {
+                let container_id = container.id.clone();
+                let replica = container.replica;
+                thread_docker
+                    .stats(
+                        &container.id,
+                        Some(StatsOptions {
+                            stream: true,
+                            one_shot: false,
+                        }),
+                    )
+                    .map(Some)
+                    .chain(stream::once(async { None }))
+                    .map(move |stats| (container_id.clone(), replica, stats))
+                    .boxed()
+            };
+
+            let containers = docker::get_scene_containers(&thread_docker, &thread_scene_name)
+                .await
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|container| container.service_id == service_id)
+                .collect::<Vec<_>>();
+
+            // Stats streams of stopped containers end right away, they are
+            // followed again when the containers start, as are the replicas
+            // created later on
+            let mut followed_container_ids = containers
+                .iter()
+                .map(|container| container.id.clone())
+                .collect::<HashSet<_>>();
+            let mut stats_stream = select_all(containers.iter().map(get_stats_stream));
+
+            let mut last_emitted: HashMap<u32, Instant> = HashMap::new();
+            loop {
+                select! {
+                    stats = stats_stream.next(), if !stats_stream.is_empty() => match stats {
+                        Some((_, replica, Some(Ok(stats)))) => {
+                            let is_due = last_emitted
+                                .get(&replica)
+                                .is_none_or(|last_emitted| last_emitted.elapsed() >= interval);
+                            if !is_due {
+                                continue;
+                            }
+                            last_emitted.insert(replica, Instant::now());
+
+                            thread_app
+                                .emit_all(
+                                    service_stats_event_name.as_ref(),
+                                    get_service_stats_event_payload(replica, &stats),
+                                )
+                                .unwrap();
+                        }
+                        Some((container_id, _, None)) => {
+                            followed_container_ids.remove(&container_id);
+                        }
+                        _ => {}
+                    },
+                    Some(container) = started_containers.next() => {
+                        if followed_container_ids.insert(container.id.clone()) {
+                            stats_stream.push(get_stats_stream(&container));
+                        }
+                    },
+                }
+            }
+        });
+
+        stats_handles.push(stats_handle);
+    }
+
+    let state = app.state::<AppState>();
+    if let Some(previous_handles) = state
+        .scene_stats_handles
+        .lock()
+        .await
+        .insert(scene_name.to_string(), stats_handles)
+    {
+        for previous_handle in previous_handles {
+            previous_handle.abort();
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn stop_emitting_scene_stats(
+    state: State<'_, AppState>,
+    scene_name: &str,
+) -> Result<(), String> {
+    let stats_handles = state.scene_stats_handles.lock().await.remove(scene_name);
+
+    if let Some(stats_handles) = stats_handles {
+        for stats_handle in stats_handles {
+            stats_handle.abort();
+        }
+    }
+
+    Ok(())
+}
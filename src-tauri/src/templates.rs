@@ -0,0 +1,455 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use serde::Serialize;
+use serde_yaml::Value;
+
+use crate::{
+    assets::get_service_assets_dirpath,
+    docker::{self, get_docker_compose_dirpath},
+    scenes,
+    services::Service,
+    state::SceneWriteLocks,
+    utils::write_file_in_place,
+    watchers,
+};
+
+/// Top-level key of the compose file holding the scene variables
+const VARIABLES_KEY: &str = "x-variables";
+
+/// Assets with this suffix are rendered next to themselves, without it
+pub const TEMPLATE_EXTENSION: &str = "tmpl";
+
+const TEMPLATE_OPENING_DELIMITER: &str = "{{";
+const TEMPLATE_CLOSING_DELIMITER: &str = "}}";
+
+#[derive(Serialize, Debug)]
+pub struct RenderedTemplate {
+    #[serde(rename = "serviceId")]
+    pub service_id: String,
+    /// Paths are relative to the service assets folder
+    #[serde(rename = "templatePath")]
+    pub template_path: String,
+    #[serde(rename = "outputPath")]
+    pub output_path: String,
+    /// Missing when some variables are not defined
+    pub content: Option<String>,
+    #[serde(rename = "missingVariables")]
+    pub missing_variables: Vec<String>,
+}
+
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(char) if char.is_ascii_alphabetic() || char == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || char == '_')
+}
+
+fn get_variable_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Reads `KEY=VALUE` lines, ignoring comments and an optional `export` prefix
+fn parse_env_file(content: &str) -> BTreeMap<String, String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (name, value) = line.split_once('=')?;
+            let name = name.trim();
+            if !is_variable_name(name) {
+                return None;
+            }
+
+            let value = value.trim();
+            let value = match (value.chars().next(), value.chars().last()) {
+                (Some(first @ ('"' | '\'')), Some(last)) if value.len() > 1 && first == last => {
+                    &value[1..value.len() - 1]
+                }
+                // Unquoted values can be followed by a comment
+                _ => value.split(" #").next().unwrap_or_default().trim_end(),
+            };
+
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Lists the variables declared in the `x-variables` section of the scene
+/// compose file
+pub fn read_scene_variables(scene_name: &str) -> Result<BTreeMap<String, String>, String> {
+    let docker_compose_file = docker::get_docker_compose_file(scene_name)?;
+    let Some(Value::Mapping(variables)) = docker_compose_file.extra.get(VARIABLES_KEY) else {
+        return Ok(BTreeMap::new());
+    };
+
+    Ok(variables
+        .iter()
+        .filter_map(|(name, value)| Some((name.as_str()?.to_string(), get_variable_value(value)?)))
+        .collect())
+}
+
+pub fn write_scene_variables(
+    scene_name: &str,
+    variables: BTreeMap<String, String>,
+) -> Result<(), String> {
+    if let Some(name) = variables.keys().find(|name| !is_variable_name(name)) {
+        return Err(format!(
+            "Invalid variable name {name:?}, it can only contain letters, digits and underscores"
+        ));
+    }
+
+    let mut docker_compose_file = docker::get_docker_compose_file(scene_name)?;
+    if variables.is_empty() {
+        docker_compose_file.extra.remove(VARIABLES_KEY);
+    } else {
+        let variables = variables
+            .into_iter()
+            .map(|(name, value)| (Value::String(name), Value::String(value)))
+            .collect();
+        docker_compose_file
+            .extra
+            .insert(VARIABLES_KEY.to_string(), Value::Mapping(variables));
+    }
+
+    docker::write_docker_compose_file(scene_name, &mut docker_compose_file)
+}
+
+/// Merges the scene variables with the `.env` file of the scene, which takes
+/// precedence so that it can hold machine specific values
+pub fn get_template_variables(scene_name: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut variables = read_scene_variables(scene_name)?;
+
+    let env_filepath = get_docker_compose_dirpath(scene_name).join(".env");
+    if env_filepath.is_file() {
+        let env_file = fs::read_to_string(&env_filepath)
+            .map_err(|err| format!("Cannot read .env file of scene {scene_name}: {err}"))?;
+        variables.extend(parse_env_file(&env_file));
+    }
+
+    Ok(variables)
+}
+
+/// Replaces `{{ NAME }}` placeholders, returning the names of the undefined
+/// variables instead of the content when there are some
+fn render_template(
+    template: &str,
+    variables: &BTreeMap<String, String>,
+) -> Result<Result<String, Vec<String>>, String> {
+    let mut content = String::with_capacity(template.len());
+    let mut missing_variables = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find(TEMPLATE_OPENING_DELIMITER) {
+        content.push_str(&rest[..start]);
+        let after_opening = &rest[start + TEMPLATE_OPENING_DELIMITER.len()..];
+        let end = after_opening
+            .find(TEMPLATE_CLOSING_DELIMITER)
+            .ok_or_else(|| {
+                let offset = template.len() - rest.len() + start;
+                let line = template[..offset].matches('\n').count() + 1;
+                format!("Unclosed placeholder on line {line}")
+            })?;
+
+        let name = after_opening[..end].trim();
+        if !is_variable_name(name) {
+            return Err(format!("Invalid variable name {name:?} in placeholder"));
+        }
+
+        match variables.get(name) {
+            Some(value) => content.push_str(value),
+            None if !missing_variables.iter().any(|missing| missing == name) => {
+                missing_variables.push(name.to_string())
+            }
+            None => {}
+        }
+
+        rest = &after_opening[end + TEMPLATE_CLOSING_DELIMITER.len()..];
+    }
+    content.push_str(rest);
+
+    if missing_variables.is_empty() {
+        Ok(Ok(content))
+    } else {
+        Ok(Err(missing_variables))
+    }
+}
+
+/// Lists the templates of the assets folder, relative to it. Symlinks are not
+/// followed so that templates cannot be read from outside the folder.
+fn find_templates(dirpath: &Path, relative_dirpath: &Path) -> Result<Vec<String>, String> {
+    let dir = fs::read_dir(dirpath)
+        .map_err(|err| format!("Cannot read assets folder {}: {err}", dirpath.display()))?;
+
+    let mut template_paths = vec![];
+    for entry in dir {
+        let entry = entry.map_err(|err| format!("Cannot read assets folder entry: {err}"))?;
+        let file_type = entry
+            .file_type()
+            .map_err(|err| format!("Cannot read assets folder entry: {err}"))?;
+        let relative_path = relative_dirpath.join(entry.file_name());
+
+        if file_type.is_dir() {
+            template_paths.extend(find_templates(&entry.path(), &relative_path)?);
+        } else if file_type.is_file()
+            && relative_path
+                .extension()
+                .is_some_and(|extension| extension == TEMPLATE_EXTENSION)
+        {
+            template_paths.push(relative_path.to_string_lossy().to_string());
+        }
+    }
+
+    template_paths.sort();
+    Ok(template_paths)
+}
+
+pub fn preview_service_templates(
+    scene_name: &str,
+    service_id: &str,
+) -> Result<Vec<RenderedTemplate>, String> {
+    let assets_dirpath = get_service_assets_dirpath(scene_name, service_id);
+    if !assets_dirpath.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let template_paths = find_templates(&assets_dirpath, Path::new(""))?;
+    if template_paths.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let variables = get_template_variables(scene_name)?;
+    template_paths
+        .into_iter()
+        .map(|template_path| {
+            let template = fs::read_to_string(assets_dirpath.join(&template_path))
+                .map_err(|err| format!("Cannot read template {template_path}: {err}"))?;
+            let (content, missing_variables) = match render_template(&template, &variables)
+                .map_err(|err| format!("Cannot render template {template_path}: {err}"))?
+            {
+                Ok(content) => (Some(content), vec![]),
+                Err(missing_variables) => (None, missing_variables),
+            };
+
+            Ok(RenderedTemplate {
+                service_id: service_id.to_string(),
+                output_path: template_path
+                    .strip_suffix(&format!(".{TEMPLATE_EXTENSION}"))
+                    .unwrap()
+                    .to_string(),
+                template_path,
+                content,
+                missing_variables,
+            })
+        })
+        .collect()
+}
+
+/// Collects the services started along with the given one, which compose
+/// starts through their dependencies
+fn get_started_services(services: Vec<Service>, service_id: Option<&str>) -> Vec<Service> {
+    let Some(service_id) = service_id else {
+        return services;
+    };
+
+    let mut started_service_ids = HashSet::new();
+    let mut pending_service_ids = vec![service_id.to_string()];
+    while let Some(service_id) = pending_service_ids.pop() {
+        if !started_service_ids.insert(service_id.clone()) {
+            continue;
+        }
+        if let Some(service) = services.iter().find(|service| service.id == service_id) {
+            pending_service_ids.extend(service.depends_on.keys().cloned());
+        }
+    }
+
+    services
+        .into_iter()
+        .filter(|service| started_service_ids.contains(&service.id))
+        .collect()
+}
+
+/// Lists the variables missing from each template in a single error, when
+/// some are
+fn get_missing_variables_error<'a>(
+    rendered_templates: impl Iterator<Item = &'a RenderedTemplate>,
+) -> Option<String> {
+    let missing_variables = rendered_templates
+        .filter(|rendered_template| !rendered_template.missing_variables.is_empty())
+        .map(|rendered_template| {
+            format!(
+                "{} ({}): {}",
+                rendered_template.template_path,
+                rendered_template.service_id,
+                rendered_template.missing_variables.join(", ")
+            )
+        })
+        .collect::<Vec<_>>();
+
+    (!missing_variables.is_empty()).then(|| {
+        format!(
+            "Cannot render asset templates, some variables are not defined in the scene variables or .env file: {}",
+            missing_variables.join("; ")
+        )
+    })
+}
+
+/// Renders the templates of the services about to be started, failing
+/// without writing anything when variables are missing. Each service is
+/// rendered with the variables of the scene it is declared in.
+pub fn render_scene_templates(
+    scene_write_locks: &SceneWriteLocks,
+    scene_name: &str,
+    service_id: Option<&str>,
+) -> Result<(), String> {
+    let services = get_started_services(scenes::read_scene_services(scene_name)?, service_id);
+
+    // Templates and rendered files belong to the assets of the scenes
+    let mut scene_names = vec![scene_name];
+    for service in &services {
+        if !scene_names.contains(&service.scene_name.as_str()) {
+            scene_names.push(&service.scene_name);
+        }
+    }
+    let _lock = scene_write_locks.lock(&scene_names);
+
+    let mut rendered_templates = vec![];
+    for service in &services {
+        for rendered_template in preview_service_templates(&service.scene_name, &service.id)? {
+            rendered_templates.push((service.scene_name.as_str(), rendered_template));
+        }
+    }
+
+    if let Some(err) =
+        get_missing_variables_error(rendered_templates.iter().map(|(_, rendered)| rendered))
+    {
+        return Err(err);
+    }
+
+    for (scene_name, rendered_template) in rendered_templates {
+        let output_filepath = get_service_assets_dirpath(scene_name, &rendered_template.service_id)
+            .join(&rendered_template.output_path);
+        let content = rendered_template.content.unwrap();
+
+        // Unchanged files are left alone to keep their modification time, the
+        // others are written in place for services mounting them directly
+        if fs::read_to_string(&output_filepath).is_ok_and(|current| current == content) {
+            continue;
+        }
+
+        watchers::record_app_writes(std::slice::from_ref(&output_filepath), || {
+            write_file_in_place(&output_filepath, content.as_bytes())
+        })
+        .map_err(|err| {
+            format!(
+                "Cannot write rendered template {}: {err}",
+                rendered_template.output_path
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_variables(variables: &[(&str, &str)]) -> BTreeMap<String, String> {
+        variables
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn render_template_replaces_placeholders() {
+        let variables = get_variables(&[("HOST", "localhost"), ("PORT", "5432")]);
+
+        assert_eq!(
+            render_template("url={{HOST}}:{{ PORT }}\n{{  HOST  }}", &variables),
+            Ok(Ok("url=localhost:5432\nlocalhost".to_string()))
+        );
+        assert_eq!(
+            render_template("no placeholders { } }}", &variables),
+            Ok(Ok("no placeholders { } }}".to_string()))
+        );
+    }
+
+    #[test]
+    fn render_template_lists_missing_variables_once() {
+        let variables = get_variables(&[("HOST", "localhost")]);
+
+        assert_eq!(
+            render_template("{{ USER }}@{{ HOST }}:{{ PORT }}/{{ USER }}", &variables),
+            Ok(Err(vec!["USER".to_string(), "PORT".to_string()]))
+        );
+    }
+
+    #[test]
+    fn render_template_rejects_invalid_placeholders() {
+        let variables = get_variables(&[]);
+
+        assert_eq!(
+            render_template("a\nb {{ HOST", &variables),
+            Err("Unclosed placeholder on line 2".to_string())
+        );
+        assert!(render_template("{{ 1HOST }}", &variables).is_err());
+        assert!(render_template("{{ HOST-NAME }}", &variables).is_err());
+        assert!(render_template("{{}}", &variables).is_err());
+    }
+
+    #[test]
+    fn parse_env_file_reads_assignments() {
+        let env_file = "# comment\n\nHOST=localhost\nexport PORT = 5432\nQUOTED=\"a # b\"\nSINGLE='c'\nCOMMENTED=d # e\nEMPTY=\n1INVALID=f\nNO_ASSIGNMENT\n";
+
+        assert_eq!(
+            parse_env_file(env_file),
+            get_variables(&[
+                ("HOST", "localhost"),
+                ("PORT", "5432"),
+                ("QUOTED", "a # b"),
+                ("SINGLE", "c"),
+                ("COMMENTED", "d"),
+                ("EMPTY", ""),
+            ])
+        );
+    }
+
+    #[test]
+    fn missing_variables_error_lists_every_template() {
+        let rendered_templates = [
+            RenderedTemplate {
+                service_id: "db".to_string(),
+                template_path: "init.sql.tmpl".to_string(),
+                output_path: "init.sql".to_string(),
+                content: None,
+                missing_variables: vec!["USER".to_string(), "PASSWORD".to_string()],
+            },
+            RenderedTemplate {
+                service_id: "web".to_string(),
+                template_path: "nginx.conf.tmpl".to_string(),
+                output_path: "nginx.conf".to_string(),
+                content: Some(String::new()),
+                missing_variables: vec![],
+            },
+        ];
+
+        assert_eq!(
+            get_missing_variables_error(rendered_templates.iter()).unwrap(),
+            "Cannot render asset templates, some variables are not defined in the scene variables or .env file: init.sql.tmpl (db): USER, PASSWORD"
+        );
+        assert_eq!(
+            get_missing_variables_error(rendered_templates[1..].iter()),
+            None
+        );
+    }
+}
@@ -1,9 +1,17 @@
 use std::{
-    path::{Path, PathBuf}, process::Command, time::SystemTime
+    fs,
+    io::Write,
+    path::{Path, PathBuf}, process::Command, time::{SystemTime, UNIX_EPOCH}
 };
 
 use chrono::{DateTime, Utc};
-use tauri::api::path::home_dir;
+use tauri::{api::path::home_dir, State};
+
+use crate::{
+    assets,
+    paths::{AssetPath, SceneName, ServiceId},
+    state::AppState,
+};
 
 pub fn create_config_folders() -> Result<(), String> {
     let scenes_path = get_config_dirpath().join("scenes");
@@ -31,16 +39,82 @@ pub fn get_config_dirpath() -> PathBuf {
     Path::new(&home_dir().unwrap()).join(".dcompose-workbench")
 }
 
+/// Writes a file through a temporary file of the same folder renamed over
+/// it, so that readers and crashes never see a partially written file
+pub fn write_file_atomically(filepath: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let file_name = filepath.file_name().unwrap_or_default().to_string_lossy();
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let temp_filepath =
+        filepath.with_file_name(format!(".{file_name}.{}-{nanos}.tmp", std::process::id()));
+
+    let result = (|| {
+        let mut temp_file = fs::File::create(&temp_filepath)?;
+        temp_file.write_all(contents)?;
+        if let Ok(metadata) = fs::metadata(filepath) {
+            temp_file.set_permissions(metadata.permissions())?;
+        }
+        temp_file.sync_all()?;
+        fs::rename(&temp_filepath, filepath)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_filepath);
+    }
+    result
+}
+
+/// Overwrites a file while keeping its inode, so that containers bind mounting
+/// the file itself see the new content. The write is not atomic: the previous
+/// content is backed up in the config folder, outside of the scenes, put back
+/// when the write fails halfway and only deleted once the file is restored or
+/// written, so that it survives a crash. Missing files are created atomically.
+pub fn write_file_in_place(filepath: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if !filepath.is_file() {
+        return write_file_atomically(filepath, contents);
+    }
+
+    let backups_dirpath = get_config_dirpath().join("backups");
+    fs::create_dir_all(&backups_dirpath)?;
+    let file_name = filepath.file_name().unwrap_or_default().to_string_lossy();
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let backup_filepath =
+        backups_dirpath.join(format!("{file_name}.{}-{nanos}.bak", std::process::id()));
+    fs::copy(filepath, &backup_filepath)?;
+
+    let write = |contents: &[u8]| {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(filepath)?;
+        file.write_all(contents)?;
+        file.sync_all()
+    };
+
+    let result = write(contents);
+    let is_restored = result.is_ok()
+        || fs::read(&backup_filepath)
+            .is_ok_and(|previous_contents| write(&previous_contents).is_ok());
+    if is_restored {
+        let _ = fs::remove_file(&backup_filepath);
+    }
+    result
+}
+
 #[tauri::command(async)]
 pub fn open_vscode(
-    scene_name: &str,
-    service_id: Option<&str>,
-    filepath: Option<&str>,
+    scene_name: SceneName,
+    service_id: Option<ServiceId>,
+    filepath: Option<AssetPath>,
 ) -> Result<(), String> {
     let mut args = vec![];
 
-    let binding = format!("/home/mia/.dcompose-workbench/scenes/{scene_name}");
-    let mut path = PathBuf::from(&binding);
+    let mut path = scene_name.dirpath();
     if let Some(service_id) = service_id {
         path = path.join(service_id);
     }
@@ -54,7 +128,8 @@ pub fn open_vscode(
 
     Command::new("code").args(args).spawn().map_err(|err| {
         format!(
-            "Unable to open vs-code in /home/mia/.dcompose-workbench/scenes/{scene_name}: {err}"
+            "Unable to open vs-code in {}: {err}",
+            scene_name.dirpath().to_string_lossy()
         )
     })?;
 
@@ -63,11 +138,13 @@ pub fn open_vscode(
 
 #[tauri::command(async)]
 pub fn copy_target_entry(
-    scene_name: &str,
-    service_id: &str,
+    state: State<'_, AppState>,
+    scene_name: SceneName,
+    service_id: ServiceId,
     source: &str,
-    target: &str,
+    target: AssetPath,
 ) -> Result<(), String> {
+    let _lock = state.scene_write_locks.lock(&[&scene_name]);
     let source_path = PathBuf::from(source);
     match source_path.try_exists() {
         Err(err) => return Err(format!("Cannot read source path {source}: {err}")),
@@ -75,11 +152,7 @@ pub fn copy_target_entry(
         _ => {}
     }
 
-    let target_path = get_config_dirpath()
-        .join("scenes")
-        .join(scene_name)
-        .join(service_id)
-        .join(target);
+    let target_path = assets::resolve_asset_path(&scene_name, &service_id, &target)?;
 
     match target_path.try_exists() {
         Err(err) => return Err(format!("Cannot read target path {source}: {err}")),
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+
+use bollard::{
+    container::{ListContainersOptions, RemoveContainerOptions},
+    secret::MountPointTypeEnum,
+    volume::ListVolumesOptions,
+    Docker,
+};
+use serde::Serialize;
+
+use crate::docker;
+
+#[derive(Serialize, Clone)]
+pub struct VolumeUser {
+    #[serde(rename = "containerName")]
+    pub container_name: String,
+    #[serde(rename = "serviceId")]
+    pub service_id: Option<String>,
+    pub project: Option<String>,
+    pub destination: Option<String>,
+    pub running: bool,
+}
+
+#[derive(Serialize, Clone)]
+pub struct SceneVolume {
+    pub name: String,
+    /// Key of the volume in the `volumes` section, missing for anonymous volumes
+    #[serde(rename = "composeName")]
+    pub compose_name: Option<String>,
+    pub anonymous: bool,
+    pub driver: String,
+    pub mountpoint: String,
+    pub size: Option<i64>,
+    pub users: Vec<VolumeUser>,
+}
+
+/// Maps every volume to the containers mounting it, whether they are running or not
+async fn get_volume_users(docker: &Docker) -> Result<HashMap<String, Vec<VolumeUser>>, String> {
+    let containers = docker
+        .list_containers(Some(ListContainersOptions::<String> {
+            all: true,
+            ..Default::default()
+        }))
+        .await
+        .map_err(|err| format!("Cannot list containers: {err}"))?;
+
+    let mut volume_users: HashMap<String, Vec<VolumeUser>> = HashMap::new();
+    for container in containers {
+        let labels = container.labels.unwrap_or_default();
+        let container_name = container
+            .names
+            .and_then(|names| names.first().cloned())
+            .map(|name| name.trim_start_matches('/').to_string())
+            .unwrap_or_default();
+
+        for mount in container.mounts.unwrap_or_default() {
+            let Some(MountPointTypeEnum::VOLUME) = mount.typ else {
+                continue;
+            };
+            let Some(volume_name) = mount.name else {
+                continue;
+            };
+
+            volume_users
+                .entry(volume_name)
+                .or_default()
+                .push(VolumeUser {
+                    container_name: container_name.clone(),
+                    service_id: labels.get("com.docker.compose.service").cloned(),
+                    project: labels.get("com.docker.compose.project").cloned(),
+                    destination: mount.destination,
+                    running: container.state.as_deref() == Some("running"),
+                });
+        }
+    }
+
+    Ok(volume_users)
+}
+
+/// Lists the named and anonymous volumes created by compose for the scene,
+/// found through their project label, and the anonymous volumes mounted by its
+/// containers that have no label
+pub async fn get_scene_volumes(scene_name: &str) -> Result<Vec<SceneVolume>, String> {
+    let docker = Docker::connect_with_socket_defaults()
+        .map_err(|error| format!("Cannot connect to docker socket: {}", error))?;
+
+    let project_name = docker::get_compose_project_name(scene_name);
+    let volume_users = get_volume_users(&docker).await?;
+
+    let scene_container_names = docker::get_scene_containers(&docker, scene_name)
+        .await?
+        .into_iter()
+        .map(|container| container.name)
+        .collect::<Vec<_>>();
+
+    // Sizes are only computed by the disk usage endpoint
+    let volume_sizes = docker
+        .df()
+        .await
+        .map_err(|err| format!("Cannot retrieve volumes disk usage: {err}"))?
+        .volumes
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|volume| {
+            let size = volume.usage_data?.size;
+            (size >= 0).then_some((volume.name, size))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let volumes = docker
+        .list_volumes::<String>(Some(ListVolumesOptions::default()))
+        .await
+        .map_err(|err| format!("Cannot list volumes: {err}"))?
+        .volumes
+        .unwrap_or_default();
+
+    let mut scene_volumes = volumes
+        .into_iter()
+        .filter_map(|volume| {
+            let users = volume_users.get(&volume.name).cloned().unwrap_or_default();
+            let compose_name = volume.labels.get("com.docker.compose.volume").cloned();
+
+            // Anonymous volumes keep their label once their container is removed
+            let is_labelled_scene_volume =
+                volume.labels.get("com.docker.compose.project") == Some(&project_name);
+            let is_anonymous_scene_volume = compose_name.is_none()
+                && users
+                    .iter()
+                    .any(|user| scene_container_names.contains(&user.container_name));
+            if !is_labelled_scene_volume && !is_anonymous_scene_volume {
+                return None;
+            }
+
+            Some(SceneVolume {
+                anonymous: compose_name.is_none(),
+                compose_name,
+                size: volume_sizes.get(&volume.name).copied(),
+                name: volume.name,
+                driver: volume.driver,
+                mountpoint: volume.mountpoint,
+                users,
+            })
+        })
+        .collect::<Vec<_>>();
+    scene_volumes.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(scene_volumes)
+}
+
+/// Deletes the given volumes of the scene. Nothing is deleted if any of them
+/// does not belong to the scene or is mounted by a running container, or by a
+/// stopped container of another scene. Stopped containers of the scene
+/// mounting them are removed first, compose recreates them on the next start.
+pub async fn delete_scene_volumes(
+    scene_name: &str,
+    volume_names: Vec<String>,
+) -> Result<(), String> {
+    let docker = Docker::connect_with_socket_defaults()
+        .map_err(|error| format!("Cannot connect to docker socket: {}", error))?;
+
+    let project_name = docker::get_compose_project_name(scene_name);
+    let scene_volumes = get_scene_volumes(scene_name).await?;
+
+    let mut volumes_in_use = vec![];
+    let mut stopped_container_names = vec![];
+    for volume_name in &volume_names {
+        let volume = scene_volumes
+            .iter()
+            .find(|volume| volume.name == *volume_name)
+            .ok_or(format!(
+                "Volume {volume_name} does not belong to scene {scene_name}"
+            ))?;
+
+        let (stopped_scene_users, other_users): (Vec<_>, Vec<_>) = volume
+            .users
+            .iter()
+            .partition(|user| !user.running && user.project.as_ref() == Some(&project_name));
+        for user in stopped_scene_users {
+            if !stopped_container_names.contains(&user.container_name) {
+                stopped_container_names.push(user.container_name.clone());
+            }
+        }
+
+        if !other_users.is_empty() {
+            let container_names = other_users
+                .iter()
+                .map(|user| user.container_name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            volumes_in_use.push(format!("{volume_name} (used by {container_names})"));
+        }
+    }
+
+    if !volumes_in_use.is_empty() {
+        return Err(format!(
+            "Cannot delete volumes that are in use: {}",
+            volumes_in_use.join(", ")
+        ));
+    }
+
+    for container_name in stopped_container_names {
+        docker
+            .remove_container(&container_name, None::<RemoveContainerOptions>)
+            .await
+            .map_err(|err| format!("Could not remove stopped container {container_name}: {err}"))?;
+    }
+
+    for volume_name in volume_names {
+        docker
+            .remove_volume(&volume_name, None)
+            .await
+            .map_err(|err| format!("Could not delete volume {volume_name}: {err}"))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,553 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync,
+    time::{Duration, Instant, SystemTime},
+};
+
+use bollard::{
+    container::{KillContainerOptions, RestartContainerOptions},
+    Docker,
+};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use tauri::{AppHandle, Manager, State};
+use tokio::{spawn, time::sleep};
+
+use crate::{
+    assets::{self, get_service_assets_dirpath},
+    docker, scenes,
+    state::{AppState, ServiceKey},
+};
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_ASSET_WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+/// Keeps the debounce well under `APP_WRITE_EXPIRY`
+const MAX_ASSET_WATCH_DEBOUNCE: Duration = Duration::from_secs(10);
+const SCENE_WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// How long the watchers may take to notice a write of the app, which is
+/// forgotten afterwards
+const APP_WRITE_EXPIRY: Duration = Duration::from_secs(60);
+
+/// Seconds docker waits for a container to stop before killing it
+const RESTART_TIMEOUT: isize = 10;
+
+/// Modification time and size of a file
+pub type FileState = (Option<SystemTime>, u64);
+
+/// State of each file of a folder, keyed by their path relative to it
+pub type FilesSnapshot = HashMap<PathBuf, FileState>;
+
+/// Snapshots the files of a folder without following symlinks, missing
+/// folders being empty
+pub fn snapshot_files(dirpath: &Path) -> FilesSnapshot {
+    let mut snapshot = HashMap::new();
+    snapshot_files_recursive(dirpath, Path::new(""), &mut snapshot);
+    snapshot
+}
+
+fn snapshot_files_recursive(dirpath: &Path, relative_dirpath: &Path, snapshot: &mut FilesSnapshot) {
+    let Ok(dir) = fs::read_dir(dirpath) else {
+        return;
+    };
+
+    for entry in dir.flatten() {
+        let Ok(metadata) = entry.path().symlink_metadata() else {
+            continue;
+        };
+        let relative_path = relative_dirpath.join(entry.file_name());
+
+        if metadata.is_dir() {
+            snapshot_files_recursive(&entry.path(), &relative_path, snapshot);
+        } else {
+            snapshot.insert(relative_path, (metadata.modified().ok(), metadata.len()));
+        }
+    }
+}
+
+/// State of the files the app itself wrote, removed ones being `None`, and
+/// when it wrote them, keyed by their absolute path
+static APP_WRITES: sync::Mutex<BTreeMap<PathBuf, (Option<FileState>, Instant)>> =
+    sync::Mutex::new(BTreeMap::new());
+
+/// Snapshots a file, or the files of a folder, keyed by their absolute path
+fn snapshot_paths(paths: &[PathBuf]) -> FilesSnapshot {
+    let mut snapshot = HashMap::new();
+    for path in paths {
+        match path.symlink_metadata() {
+            Ok(metadata) if metadata.is_dir() => snapshot.extend(
+                snapshot_files(path)
+                    .into_iter()
+                    .map(|(relative_path, state)| (path.join(relative_path), state)),
+            ),
+            Ok(metadata) => {
+                snapshot.insert(path.clone(), (metadata.modified().ok(), metadata.len()));
+            }
+            Err(_) => {}
+        }
+    }
+    snapshot
+}
+
+/// Runs a write made by the app as a side effect, such as rendering templates
+/// or undoing an edit, remembering the files it changed under the given paths
+/// so that the asset watchers do not reload services for them
+pub fn record_app_writes<T>(paths: &[PathBuf], write: impl FnOnce() -> T) -> T {
+    let previous_snapshot = snapshot_paths(paths);
+    let result = write();
+    let current_snapshot = snapshot_paths(paths);
+
+    let now = Instant::now();
+    let mut app_writes = APP_WRITES.lock().unwrap();
+    app_writes.retain(|_, (_, written_at)| now.duration_since(*written_at) < APP_WRITE_EXPIRY);
+    for path in get_changed_paths(&previous_snapshot, &current_snapshot) {
+        let state = current_snapshot.get(&path).copied();
+        app_writes.insert(path, (state, now));
+    }
+
+    result
+}
+
+/// Whether the file is in the state the app left it in, rather than changed
+/// by another program since
+fn is_written_by_app(path: &Path, state: Option<&FileState>) -> bool {
+    APP_WRITES
+        .lock()
+        .unwrap()
+        .get(path)
+        .is_some_and(|(app_state, written_at)| {
+            app_state.as_ref() == state && written_at.elapsed() < APP_WRITE_EXPIRY
+        })
+}
+
+/// Lists the files added, removed or modified between two snapshots
+pub fn get_changed_paths(previous: &FilesSnapshot, current: &FilesSnapshot) -> BTreeSet<PathBuf> {
+    let removed_paths = previous.keys().filter(|path| !current.contains_key(*path));
+    let added_or_modified_paths = current
+        .iter()
+        .filter(|(path, metadata)| previous.get(*path) != Some(metadata))
+        .map(|(path, _)| path);
+
+    removed_paths
+        .chain(added_or_modified_paths)
+        .cloned()
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum AssetWatchAction {
+    Restart,
+    /// Sends a signal such as `SIGHUP` to the containers, for services that
+    /// reload their configuration without restarting
+    Signal {
+        signal: String,
+    },
+}
+
+#[derive(Serialize, Clone)]
+struct ServiceAssetsReloadedEventPayload {
+    #[serde(rename = "changedPaths")]
+    changed_paths: Vec<String>,
+    action: AssetWatchAction,
+    #[serde(rename = "containerNames")]
+    container_names: Vec<String>,
+    error: Option<String>,
+}
+
+/// Keeps the changed paths that are mounted in the service containers,
+/// through a bind volume of the file itself or of one of its folders
+fn get_mounted_paths(
+    scene_name: &str,
+    service_id: &str,
+    changed_paths: &BTreeSet<PathBuf>,
+) -> Vec<String> {
+    let Ok(docker_compose_file) = docker::get_docker_compose_file(scene_name) else {
+        return vec![];
+    };
+    let mounted_paths = assets::get_service_asset_usages(&docker_compose_file, service_id)
+        .into_iter()
+        .filter(|usage| usage.field == "volumes")
+        .map(|usage| PathBuf::from(usage.path))
+        .collect::<Vec<_>>();
+
+    changed_paths
+        .iter()
+        .filter(|changed_path| {
+            mounted_paths
+                .iter()
+                .any(|mounted_path| changed_path.starts_with(mounted_path))
+        })
+        .map(|changed_path| changed_path.to_string_lossy().to_string())
+        .collect()
+}
+
+async fn reload_service_containers(
+    docker: &Docker,
+    scene_name: &str,
+    service_id: &str,
+    action: &AssetWatchAction,
+) -> Result<Vec<String>, String> {
+    let containers = docker::get_scene_containers(docker, scene_name)
+        .await?
+        .into_iter()
+        .filter(|container| container.service_id == service_id)
+        .collect::<Vec<_>>();
+
+    for container in &containers {
+        match action {
+            AssetWatchAction::Restart => docker
+                .restart_container(
+                    &container.id,
+                    Some(RestartContainerOptions { t: RESTART_TIMEOUT }),
+                )
+                .await
+                .map_err(|err| format!("Cannot restart container {}: {err}", container.name))?,
+            AssetWatchAction::Signal { signal } => docker
+                .kill_container(
+                    &container.id,
+                    Some(KillContainerOptions {
+                        signal: signal.as_str(),
+                    }),
+                )
+                .await
+                .map_err(|err| {
+                    format!(
+                        "Cannot send {signal} to container {}: {err}",
+                        container.name
+                    )
+                })?,
+        }
+    }
+
+    Ok(containers
+        .into_iter()
+        .map(|container| container.name)
+        .collect())
+}
+
+/// Watches the assets folder of a service, restarting or signaling its
+/// containers once changes to mounted files have settled. Files written by
+/// the app as a side effect are ignored, edits made through the app's asset
+/// commands are not.
+pub async fn start_watching_service_assets(
+    app: &AppHandle,
+    scene_name: &str,
+    service_id: &str,
+    action: AssetWatchAction,
+    debounce: Option<u64>,
+) -> Result<(), String> {
+    if let AssetWatchAction::Signal { signal } = &action {
+        if signal.is_empty() || !signal.chars().all(|char| char.is_ascii_alphanumeric()) {
+            return Err(format!("Invalid signal {signal:?}"));
+        }
+    }
+
+    let docker = Docker::connect_with_socket_defaults()
+        .map_err(|error| format!("Cannot connect to docker socket: {}", error))?;
+
+    // Services of included scenes keep their assets in their own scene
+    let service_scene_name = scenes::read_scene_services(scene_name)?
+        .into_iter()
+        .find(|service| service.id == service_id)
+        .ok_or(format!(
+            "Cannot find service {service_id} in scene {scene_name}"
+        ))?
+        .scene_name;
+
+    let debounce = debounce
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_ASSET_WATCH_DEBOUNCE)
+        .min(MAX_ASSET_WATCH_DEBOUNCE);
+
+    let thread_app = app.to_owned();
+    let thread_scene_name = scene_name.to_string();
+    let thread_service_id = service_id.to_string();
+    let watch_handle = spawn(async move {
+        let event_name = format!("{thread_scene_name}-{thread_service_id}-assets-reloaded-event");
+        let assets_dirpath = get_service_assets_dirpath(&service_scene_name, &thread_service_id);
+
+        let mut snapshot = snapshot_files(&assets_dirpath);
+        loop {
+            sleep(WATCH_INTERVAL).await;
+
+            let mut current_snapshot = snapshot_files(&assets_dirpath);
+            let mut changed_paths = get_changed_paths(&snapshot, &current_snapshot);
+            if changed_paths.is_empty() {
+                continue;
+            }
+
+            // Editors often write files in several steps, wait until they are done
+            loop {
+                sleep(debounce).await;
+                let next_snapshot = snapshot_files(&assets_dirpath);
+                let next_changed_paths = get_changed_paths(&current_snapshot, &next_snapshot);
+                current_snapshot = next_snapshot;
+                if next_changed_paths.is_empty() {
+                    break;
+                }
+                changed_paths.extend(next_changed_paths);
+            }
+            changed_paths.retain(|changed_path| {
+                let state = current_snapshot.get(changed_path);
+                !is_written_by_app(&assets_dirpath.join(changed_path), state)
+            });
+            snapshot = current_snapshot;
+
+            let mounted_paths =
+                get_mounted_paths(&service_scene_name, &thread_service_id, &changed_paths);
+            if mounted_paths.is_empty() {
+                continue;
+            }
+
+            let (container_names, error) = match reload_service_containers(
+                &docker,
+                &thread_scene_name,
+                &thread_service_id,
+                &action,
+            )
+            .await
+            {
+                Ok(container_names) => (container_names, None),
+                Err(err) => (vec![], Some(err)),
+            };
+
+            thread_app
+                .emit_all(
+                    event_name.as_ref(),
+                    ServiceAssetsReloadedEventPayload {
+                        changed_paths: mounted_paths,
+                        action: action.clone(),
+                        container_names,
+                        error,
+                    },
+                )
+                .unwrap();
+        }
+    });
+
+    let state = app.state::<AppState>();
+    if let Some(previous_handle) = state.service_asset_watch_handles.lock().await.insert(
+        ServiceKey {
+            scene_name: scene_name.to_string(),
+            service_id: service_id.to_string(),
+        },
+        watch_handle,
+    ) {
+        previous_handle.abort();
+    }
+
+    Ok(())
+}
+
+pub async fn stop_watching_service_assets(
+    state: State<'_, AppState>,
+    scene_name: &str,
+    service_id: &str,
+) -> Result<(), String> {
+    let watch_handle = state
+        .service_asset_watch_handles
+        .lock()
+        .await
+        .remove(&ServiceKey {
+            scene_name: scene_name.to_string(),
+            service_id: service_id.to_string(),
+        });
+
+    if let Some(watch_handle) = watch_handle {
+        watch_handle.abort();
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, Clone)]
+struct SceneChangedEventPayload {
+    #[serde(rename = "sceneName")]
+    scene_name: String,
+    /// Scenes whose compose file changed, the scene itself or included ones
+    #[serde(rename = "changedScenes")]
+    changed_scenes: Vec<String>,
+    #[serde(rename = "addedServices")]
+    added_services: Vec<String>,
+    #[serde(rename = "removedServices")]
+    removed_services: Vec<String>,
+    #[serde(rename = "modifiedServices")]
+    modified_services: Vec<String>,
+    /// Set when the compose file cannot be read, typically while being edited
+    error: Option<String>,
+}
+
+/// Lists the scene and the scenes it includes, directly or not
+fn get_scene_file_scene_names(scene_name: &str) -> Vec<String> {
+    let mut scene_names = vec![scene_name.to_string()];
+    let mut index = 0;
+    while index < scene_names.len() {
+        for included_scene in scenes::read_included_scenes(&scene_names[index]).unwrap_or_default()
+        {
+            if !scene_names.contains(&included_scene.name) {
+                scene_names.push(included_scene.name);
+            }
+        }
+        index += 1;
+    }
+
+    scene_names
+}
+
+fn snapshot_compose_files(scene_names: &[String]) -> FilesSnapshot {
+    scene_names
+        .iter()
+        .map(|scene_name| {
+            let filepath =
+                docker::get_docker_compose_dirpath(scene_name).join("docker-compose.yml");
+            let metadata = filepath.metadata().ok();
+            (
+                PathBuf::from(scene_name),
+                (
+                    metadata
+                        .as_ref()
+                        .and_then(|metadata| metadata.modified().ok()),
+                    metadata.map(|metadata| metadata.len()).unwrap_or_default(),
+                ),
+            )
+        })
+        .collect()
+}
+
+/// Reads the definitions of the services of the scene and of the scenes it
+/// includes, to tell which ones changed
+fn read_service_definitions(scene_names: &[String]) -> Result<BTreeMap<String, Value>, String> {
+    let mut service_definitions = BTreeMap::new();
+    for scene_name in scene_names {
+        for (service_id, service) in docker::get_docker_compose_file(scene_name)?.services {
+            let service = serde_yaml::to_value(service)
+                .map_err(|err| format!("Cannot serialize service {service_id}: {err}"))?;
+            service_definitions.insert(service_id, service);
+        }
+    }
+
+    Ok(service_definitions)
+}
+
+/// Watches the compose files of a scene for changes made outside of the app,
+/// emitting `scene-changed` events and following added or removed services
+/// in the status tasks of the scene
+pub async fn start_watching_scene(app: &AppHandle, scene_name: &str) -> Result<(), String> {
+    let scene_names = get_scene_file_scene_names(scene_name);
+    let service_definitions = read_service_definitions(&scene_names)?;
+
+    let thread_app = app.to_owned();
+    let thread_scene_name = scene_name.to_string();
+    let watch_handle = spawn(async move {
+        let mut scene_names = scene_names;
+        let mut service_definitions = service_definitions;
+        let mut snapshot = snapshot_compose_files(&scene_names);
+        loop {
+            sleep(WATCH_INTERVAL).await;
+
+            if snapshot_compose_files(&scene_names) == snapshot {
+                continue;
+            }
+            sleep(SCENE_WATCH_DEBOUNCE).await;
+
+            // Includes may have changed along with the files
+            let current_scene_names = get_scene_file_scene_names(&thread_scene_name);
+            let current_snapshot = snapshot_compose_files(&current_scene_names);
+
+            // Scenes included or detached in the meantime only show up through
+            // the change of the including compose file
+            let changed_scenes = current_scene_names
+                .iter()
+                .filter(|scene_name| scene_names.contains(scene_name))
+                .filter(|scene_name| {
+                    let scene_name_path = Path::new(scene_name.as_str());
+                    snapshot.get(scene_name_path) != current_snapshot.get(scene_name_path)
+                        && !docker::get_docker_compose_file_hash(scene_name).is_some_and(|hash| {
+                            docker::is_docker_compose_file_written_by_app(scene_name, &hash)
+                        })
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            scene_names = current_scene_names;
+            snapshot = current_snapshot;
+
+            // The app's own edits are followed silently, the UI already knows
+            if changed_scenes.is_empty() {
+                if let Ok(current_service_definitions) = read_service_definitions(&scene_names) {
+                    service_definitions = current_service_definitions;
+                }
+                continue;
+            }
+
+            let mut payload = SceneChangedEventPayload {
+                scene_name: thread_scene_name.clone(),
+                changed_scenes,
+                added_services: vec![],
+                removed_services: vec![],
+                modified_services: vec![],
+                error: None,
+            };
+
+            match read_service_definitions(&scene_names) {
+                Ok(current_service_definitions) => {
+                    for (service_id, service) in &current_service_definitions {
+                        match service_definitions.get(service_id) {
+                            None => payload.added_services.push(service_id.clone()),
+                            Some(previous_service) if previous_service != service => {
+                                payload.modified_services.push(service_id.clone())
+                            }
+                            Some(_) => {}
+                        }
+                    }
+                    payload.removed_services = service_definitions
+                        .keys()
+                        .filter(|service_id| !current_service_definitions.contains_key(*service_id))
+                        .cloned()
+                        .collect();
+                    service_definitions = current_service_definitions;
+
+                    if !payload.added_services.is_empty() || !payload.removed_services.is_empty() {
+                        if let Err(err) =
+                            docker::restart_emitting_scene_status(&thread_app, &thread_scene_name)
+                                .await
+                        {
+                            payload.error = Some(err);
+                        }
+                    }
+                }
+                // The previous definitions are kept to diff against the next valid file
+                Err(err) => payload.error = Some(err),
+            }
+
+            thread_app.emit_all("scene-changed", payload).unwrap();
+        }
+    });
+
+    let state = app.state::<AppState>();
+    if let Some(previous_handle) = state
+        .scene_watch_handles
+        .lock()
+        .await
+        .insert(scene_name.to_string(), watch_handle)
+    {
+        previous_handle.abort();
+    }
+
+    Ok(())
+}
+
+pub async fn stop_watching_scene(
+    state: State<'_, AppState>,
+    scene_name: &str,
+) -> Result<(), String> {
+    let watch_handle = state.scene_watch_handles.lock().await.remove(scene_name);
+
+    if let Some(watch_handle) = watch_handle {
+        watch_handle.abort();
+    }
+
+    Ok(())
+}
@@ -12,6 +12,7 @@ export type CustomEdgeData = {
   sceneName: string
   sourceServiceId: string
   targetServiceId: string
+  composeFileHash?: string
 }
 
 export default function CustomEdge(props: EdgeProps<CustomEdgeData>) {
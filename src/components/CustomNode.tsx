@@ -17,6 +17,7 @@ export type CustomNodeData = {
   serviceId: string
   serviceType?: string
   serviceSceneName: string
+  composeFileHash?: string
   reloadScene: () => void
   onDeleteService: (serviceId: string) => void
   onDetachScene: (sceneName: string, externalSceneName: string) => void
@@ -12,16 +12,22 @@ const dependsOnConditionOptions = ['service_started', 'service_healthy', 'servic
 export default function EdgeDrawer(props: Edge<CustomEdgeData>) {
   const onConditionChange = useCallback((event: SelectChangeEvent) => {
     invoke('set_dependency_condition', {
+      composeFileHash: props.data?.composeFileHash,
       condition: event.target.value,
       sceneName: props.data?.sceneName,
       source: props.source,
       target: props.target,
     }).catch(error => message(error as string, { title: 'Error', type: 'error' }));
-  }, [props.data?.sceneName, props.source, props.target]);
+  }, [props.data?.composeFileHash, props.data?.sceneName, props.source, props.target]);
 
   const { setEdges } = useReactFlow();
   const onDelete = useCallback(() => {
-    invoke('delete_dependency', { sceneName: props.data?.sceneName, source: props.source, target: props.target })
+    invoke('delete_dependency', {
+      composeFileHash: props.data?.composeFileHash,
+      sceneName: props.data?.sceneName,
+      source: props.source,
+      target: props.target,
+    })
       .then(() => {
         setEdges(edges => edges.filter(edge => edge.id !== props.id));
       })
@@ -9,23 +9,28 @@ import { appDataDir } from '@tauri-apps/api/path';
 import type { FormEvent } from 'react';
 import React, { useCallback, useEffect, useState } from 'react';
 
-import type { ServiceAssets } from '../types/service';
+import type { AssetUsage, ServiceAssetEntries, ServiceAssets } from '../types/service';
 
 type AssetsListProps = {
-  assets: ServiceAssets
+  assets: ServiceAssetEntries
   openVsCode: (filepath?: string) => void
   basePath: string
 }
 
+function formatUsage(usage: AssetUsage) {
+  return usage.target ? `${usage.field}: ${usage.target}` : usage.field;
+}
+
 function AssetsList(props: AssetsListProps) {
   return Object.entries(props.assets)
     .sort(([aName, aValue], [bName, bValue]) => {
-      if (aValue === null) { return 1; }
-      if (bValue === null) { return -1; }
+      if (aValue.children === null) { return 1; }
+      if (bValue.children === null) { return -1; }
       return aName > bName ? 1 : -1;
     })
-    .map(([entryName, children]) => {
+    .map(([entryName, { children, usages }]) => {
       const filePath = `${props.basePath}/${entryName}`;
+      const title = usages.map(formatUsage).join('\n');
 
       if (children) {
         return (
@@ -33,6 +38,7 @@ function AssetsList(props: AssetsListProps) {
             itemId={filePath}
             key={filePath}
             label={entryName}
+            title={title}
           >
             <AssetsList
               assets={children}
@@ -49,6 +55,7 @@ function AssetsList(props: AssetsListProps) {
         key={filePath}
         label={entryName}
         onDoubleClick={() => props.openVsCode(filePath)}
+        title={title}
       />;
     });
 }
@@ -59,7 +66,7 @@ type EditServiceAssetsProps = {
 }
 
 export default function EditServiceAssets(props: EditServiceAssetsProps) {
-  const [assets, setAssets] = useState<ServiceAssets>({});
+  const [assets, setAssets] = useState<ServiceAssets>({ entries: {}, usages: [], missingMounts: [] });
   const loadAssets = useCallback(() => {
     invoke<ServiceAssets>('get_service_assets', { sceneName: props.sceneName, serviceId: props.serviceId })
       .then(assets => {
@@ -163,7 +170,7 @@ export default function EditServiceAssets(props: EditServiceAssetsProps) {
         </div>
 
         <SimpleTreeView className='mb-4' disableSelection={true}>
-          <AssetsList assets={assets} basePath='./' openVsCode={openVsCode} />
+          <AssetsList assets={assets.entries} basePath='./' openVsCode={openVsCode} />
         </SimpleTreeView>
 
         <div className='flex justify-center my-4'>
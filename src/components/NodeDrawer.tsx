@@ -32,6 +32,7 @@ export default function NodeDrawer(props: NodeProps<CustomNodeData> & NodeDrawer
   const onEditService = useCallback((serviceId: string, code: string) => {
     invoke('update_service', {
       code,
+      composeFileHash: props.data?.composeFileHash,
       previousServiceId: props.data?.serviceId,
       sceneName: props.data?.sceneName,
       serviceId,
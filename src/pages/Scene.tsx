@@ -7,7 +7,7 @@ import { invoke } from '@tauri-apps/api';
 import { message } from '@tauri-apps/api/dialog';
 import dagre from 'dagre';
 import { useConfirm } from 'material-ui-confirm';
-import React, { useCallback, useEffect, useMemo, useState } from 'react';
+import React, { useCallback, useEffect, useMemo, useRef, useState } from 'react';
 import { useParams } from 'react-router-dom';
 import type { Connection, Edge, EdgeChange, Node, NodeChange } from 'reactflow';
 import { Background, ControlButton, Controls, MiniMap, Position, default as ReactFlow, addEdge, useEdgesState, useNodesState } from 'reactflow';
@@ -72,12 +72,17 @@ export default function Scene() {
   if (!sceneName) { throw new Error(); }
 
   const [serviceIds, setServiceIds] = useState<string[]>([]);
+  // Sent back with the edits so that outside changes made since the scene was loaded are not overwritten
+  const composeFileHashRef = useRef<string>();
   const loadScene = useCallback(() => invoke<Service[]>('get_scene_services', { sceneName })
     .then(services => {
       setServiceIds(services.map(service => service.id));
+      const composeFileHash = services.find(service => service.sceneName === sceneName)?.composeFileHash;
+      composeFileHashRef.current = composeFileHash;
 
       const sceneNodes: Node<CustomNodeData>[] = services.map(service => ({
         data: {
+          composeFileHash,
           onDeleteService: (serviceId: string) => { onDeleteService(serviceId).catch(() => {}); },
           onDetachScene,
           reloadScene,
@@ -97,6 +102,7 @@ export default function Scene() {
           sceneEdges.push({
             animated: true,
             data: {
+              composeFileHash,
               condition: definition.condition,
               sceneName,
               sourceServiceId: service.id,
@@ -136,12 +142,18 @@ export default function Scene() {
   }, [loadScene, startEmittingSceneStatus, stopEmittingSceneStatus]);
 
   const onConnect = useCallback((connection: Connection) => {
-    invoke('create_dependency', { sceneName, source: connection.source, target: connection.target })
+    invoke('create_dependency', {
+      composeFileHash: composeFileHashRef.current,
+      sceneName,
+      source: connection.source,
+      target: connection.target,
+    })
       .then(() => {
         setEdges((edges) => addEdge({
           ...connection,
           animated: true,
           data: {
+            composeFileHash: composeFileHashRef.current,
             sceneName,
             sourceServiceId: connection.source,
             targetServiceId: connection.target,
@@ -155,7 +167,12 @@ export default function Scene() {
   const onEdgesDelete = useCallback(async (edgesToDelete: Edge[]) => {
     for (const edge of edgesToDelete) {
       try {
-        await invoke('delete_dependency', { sceneName, source: edge.source, target: edge.target });
+        await invoke('delete_dependency', {
+          composeFileHash: composeFileHashRef.current,
+          sceneName,
+          source: edge.source,
+          target: edge.target,
+        });
       } catch (error) {
         await message(error as string, { title: 'Error', type: 'error' });
       }
@@ -201,7 +218,7 @@ export default function Scene() {
       description: `The service "${serviceId}" will be deleted along with its configuration and local assets. Are you sure you want to proceed?`,
       title: 'Delete service',
     }).then(() => {
-      invoke('delete_service', { sceneName, serviceId }).catch(error => {
+      invoke('delete_service', { composeFileHash: composeFileHashRef.current, sceneName, serviceId }).catch(error => {
         message(error as string, { title: 'Error', type: 'error' }).catch(() => {});
       });
     }).catch(() => {});
@@ -216,7 +233,11 @@ export default function Scene() {
       description: `The scene "${externalSceneName}" will be detached from "${currentSceneName}". Are you sure you want to proceed?`,
       title: 'Detach scene',
     }).then(() => {
-      invoke('detach_scene', { sceneName: currentSceneName, sceneNameToDetach: externalSceneName })
+      invoke('detach_scene', {
+        composeFileHash: composeFileHashRef.current,
+        sceneName: currentSceneName,
+        sceneNameToDetach: externalSceneName,
+      })
         .then(() => reloadScene())
         .catch(error => {
           message(error as string, { title: 'Error', type: 'error' }).catch(() => {});
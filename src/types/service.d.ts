@@ -12,6 +12,7 @@ export type Service = {
   type?: string
   dependsOn: Record<string, ServiceDependency>
   sceneName: string
+  composeFileHash: string
 }
 
 export type ServiceYaml = Record<string, unknown>
@@ -20,6 +21,22 @@ export type ServiceDependency = {
   condition: DependsOnCondition
 }
 
+export type AssetUsage = {
+  field: 'volumes' | 'env_file' | 'configs'
+  reference: string
+  path: string
+  target?: string
+}
+
+export type ServiceAssetEntries = {
+  [key: string]: {
+    children: ServiceAssetEntries | null
+    usages: AssetUsage[]
+  }
+}
+
 export type ServiceAssets = {
-  [key: string]: ServiceAssets | null
+  entries: ServiceAssetEntries
+  usages: AssetUsage[]
+  missingMounts: AssetUsage[]
 }